@@ -2,10 +2,10 @@ use crate::aabb::AABB;
 use crate::hittable::*;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::stats;
 use crate::vec3::*;
 
-use rand::prelude::*;
-
 #[derive(Clone, Debug)]
 pub enum Plane {
     XY,
@@ -65,7 +65,8 @@ impl<M: Material> AARect<M> {
 }
 
 impl<M: Sync + Send + Material + 'static> Hittable for AARect<M> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::primitive_test();
         let (k_axis, a_axis, b_axis, outward_normal) = match &self.plane {
             Plane::XY => (2, 0, 1, Vec3::new(0.0, 0.0, 1.0)),
             Plane::XZ => (1, 0, 2, Vec3::new(0.0, 1.0, 0.0)),
@@ -74,7 +75,7 @@ impl<M: Sync + Send + Material + 'static> Hittable for AARect<M> {
 
         let t = (self.k - r.orig[k_axis]) / r.dir[k_axis];
 
-        if t < t_min || t > t_max {
+        if t.is_nan() || t < t_min || t > t_max {
             return None;
         }
 
@@ -123,7 +124,7 @@ impl<M: Sync + Send + Material + 'static> Hittable for AARect<M> {
     }
 
     fn pdf_value(&self, orig: Point3, v: Vec3) -> f32 {
-        if let Some(hit) = self.hit(&Ray::new(orig, v, 0.0), 0.001, std::f32::INFINITY) {
+        if let Some(hit) = self.hit(&Ray::new(orig, v, 0.0), 0.001, f32::INFINITY) {
             let area = (self.a1 - self.a0) * (self.b1 - self.b0);
 
             let distance_squared = hit.t.powi(2) * v.length_squared();
@@ -136,28 +137,43 @@ impl<M: Sync + Send + Material + 'static> Hittable for AARect<M> {
         0.0
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
-        let mut rng = rand::thread_rng();
+    fn random(&self, orig: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let a = self.a0 + u * (self.a1 - self.a0);
+        let b = self.b0 + v * (self.b1 - self.b0);
         let random_point = match &self.plane {
-            Plane::XY => Point3::new(
-                rng.gen_range(self.a0..self.a1),
-                rng.gen_range(self.b0..self.b1),
-                self.k,
-            ),
-            Plane::XZ => Point3::new(
-                rng.gen_range(self.a0..self.a1),
-                self.k,
-                rng.gen_range(self.b0..self.b1),
-            ),
-            Plane::YZ => Point3::new(
-                self.k,
-                rng.gen_range(self.a0..self.a1),
-                rng.gen_range(self.b0..self.b1),
-            ),
+            Plane::XY => Point3::new(a, b, self.k),
+            Plane::XZ => Point3::new(a, self.k, b),
+            Plane::YZ => Point3::new(self.k, a, b),
         };
 
         random_point - orig
     }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let (u, v) = sampler.get_2d();
+        let a = self.a0 + u * (self.a1 - self.a0);
+        let b = self.b0 + v * (self.b1 - self.b0);
+
+        let (p, normal) = match &self.plane {
+            Plane::XY => (Point3::new(a, b, self.k), Vec3::new(0.0, 0.0, 1.0)),
+            Plane::XZ => (Point3::new(a, self.k, b), Vec3::new(0.0, 1.0, 0.0)),
+            Plane::YZ => (Point3::new(self.k, a, b), Vec3::new(1.0, 0.0, 0.0)),
+        };
+
+        let hr = HitRecord {
+            p,
+            normal,
+            t: 0.0,
+            u,
+            v,
+            front_face: true,
+            material: &self.material,
+        };
+        let area = (self.a1 - self.a0) * (self.b1 - self.b0);
+
+        Some((hr, 1.0 / area))
+    }
 }
 
 pub struct RectBox {
@@ -234,7 +250,7 @@ impl RectBox {
 }
 
 impl Hittable for RectBox {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         self.sides.hit(r, t_min, t_max)
     }
     fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<AABB> {
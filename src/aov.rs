@@ -0,0 +1,101 @@
+use crate::hittable::{HitRecord, Hittable};
+use crate::integrator::Radiance;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Buffers that can be written next to the final image. The first hit ones describe what
+// the camera ray hit, the light ones split the final image by bounces so they add up to
+// it. Rays that hit nothing leave the first hit buffers black.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aov {
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    Emission,
+    Direct,
+    Indirect,
+}
+
+impl Aov {
+    pub const ALL: &'static [Aov] = &[
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::Emission,
+        Aov::Direct,
+        Aov::Indirect,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Emission => "emission",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|aov| aov.name() == name)
+    }
+
+    // light that took this many bounces to reach the camera goes in this buffer
+    pub fn takes_bounces(&self, bounces: u32) -> bool {
+        match self {
+            Aov::Emission => bounces == 0,
+            Aov::Direct => bounces == 1,
+            Aov::Indirect => bounces > 1,
+            _ => false,
+        }
+    }
+
+    // whether the buffer holds light, which exposure scales like the image
+    pub fn is_light(&self) -> bool {
+        matches!(self, Aov::Emission | Aov::Direct | Aov::Indirect)
+    }
+
+    fn needs_hit(&self) -> bool {
+        matches!(self, Aov::Albedo | Aov::Normal | Aov::Depth | Aov::Position)
+    }
+
+    fn value(&self, ray: &Ray, hit: Option<&HitRecord>, radiance: &Radiance) -> Color {
+        match (self, hit) {
+            (Aov::Emission, _) => radiance.emitted,
+            (Aov::Direct, _) => radiance.direct,
+            (Aov::Indirect, _) => radiance.indirect,
+            (_, None) => Color::new_empty(),
+            (Aov::Albedo, Some(hit)) => hit.material.albedo(hit),
+            (Aov::Normal, Some(hit)) => hit.normal,
+            (Aov::Depth, Some(hit)) => {
+                let depth = hit.t * ray.dir.length();
+                Color::new(depth, depth, depth)
+            }
+            (Aov::Position, Some(hit)) => hit.p,
+        }
+    }
+}
+
+// Adds what one camera sample along `ray` contributes to each of `aovs` into `sums`.
+pub fn accumulate(
+    aovs: &[Aov],
+    sums: &mut [Color],
+    ray: &Ray,
+    world: &dyn Hittable,
+    radiance: &Radiance,
+) {
+    // the integrator already counted this ray
+    let hit = if aovs.iter().any(Aov::needs_hit) {
+        world.hit(ray, 0.001, f32::INFINITY)
+    } else {
+        None
+    };
+
+    for (sum, aov) in sums.iter_mut().zip(aovs) {
+        *sum += aov.value(ray, hit.as_ref(), radiance);
+    }
+}
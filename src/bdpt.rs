@@ -0,0 +1,518 @@
+use crate::film::Splat;
+use crate::hittable::*;
+use crate::integrator::*;
+use crate::material::*;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::stats::{self, RayKind};
+use crate::vec3::*;
+
+// One vertex of a camera or light subpath. Both densities are per unit area at this
+// vertex: `pdf_fwd` for reaching it the way its subpath did, `pdf_rev` for reaching it
+// from the other end. The MIS weights are built from their ratios.
+#[derive(Clone, Copy)]
+struct Vertex<'a> {
+    p: Point3,
+    // None for the camera
+    hit: Option<HitRecord<'a>>,
+    beta: Color,
+    pdf_fwd: f32,
+    pdf_rev: f32,
+    // specular bounces and surfaces that don't scatter can't be connected to
+    delta: bool,
+}
+
+impl<'a> Vertex<'a> {
+    fn camera(p: Point3) -> Self {
+        Self {
+            p,
+            hit: None,
+            beta: Color::new(1.0, 1.0, 1.0),
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn surface(hit: HitRecord<'a>, beta: Color) -> Self {
+        Self {
+            p: hit.p,
+            hit: Some(hit),
+            beta,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    // turns a solid angle density of leaving this vertex towards `next` into an area
+    // density at `next`
+    fn convert_density(&self, pdf: f32, next: &Vertex) -> f32 {
+        let d = next.p - self.p;
+        let distance_squared = d.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+
+        match &next.hit {
+            Some(hit) => pdf * hit.normal.dot(d).abs() / (distance_squared * d.length()),
+            None => pdf / distance_squared,
+        }
+    }
+
+    // Area density at `next` of continuing the subpath from here after arriving from
+    // `prev`. A surface vertex without `prev` starts a light subpath.
+    fn pdf(
+        &self,
+        scene: &SceneView,
+        prev: Option<&Vertex>,
+        next: &Vertex,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> f32 {
+        let pdf = match (&self.hit, prev) {
+            (None, _) if scene.camera.is_pinhole() => scene.camera.importance_pdf(next.p - self.p),
+            (None, _) => 0.0,
+            (Some(hit), None) => emission_pdf(hit, next.p),
+            (Some(hit), Some(prev)) => scattering(hit, prev.p, next.p, time, sampler).1,
+        };
+
+        self.convert_density(pdf, next)
+    }
+}
+
+// Bidirectional path tracer. Every sample traces a subpath from the camera and one from a
+// point on the lights list, then joins every prefix of one to every prefix of the other.
+// Each way of building a path gets a multiple importance sampling weight, so lights
+// hidden behind geometry are found by the light subpath and caustics through glass by
+// connecting it straight to the camera, which only works for pinhole cameras.
+pub struct BdptIntegrator {
+    pub max_depth: u32,
+    pub rr_depth: u32,
+    pub heuristic: MisHeuristic,
+}
+
+impl BdptIntegrator {
+    pub fn new(max_depth: u32, rr_depth: u32, heuristic: MisHeuristic) -> Self {
+        Self {
+            max_depth,
+            rr_depth,
+            heuristic,
+        }
+    }
+
+    // Extends `path` along `ray` until it has `max_vertices` vertices, escapes or gets
+    // absorbed. `pdf` is the solid angle density `ray` was sampled with. Returns what the
+    // background contributes if the path escapes.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk<'a>(
+        &self,
+        scene: &SceneView<'a>,
+        mut ray: Ray,
+        mut beta: Color,
+        mut pdf: f32,
+        path: &mut Vec<Vertex<'a>>,
+        max_vertices: usize,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut bounce = 0;
+
+        while path.len() < max_vertices {
+            // only the camera subpath starts at a vertex without a hit
+            let kind = match (path.len(), path[0].hit.is_none()) {
+                (_, false) => RayKind::Light,
+                (1, true) => RayKind::Camera,
+                _ => RayKind::Secondary,
+            };
+            let hit = match scene.hit(&ray, kind) {
+                Some(hit) => hit,
+                None => return beta * scene.background,
+            };
+
+            let prev = path.len() - 1;
+            let mut vertex = Vertex::surface(hit, beta);
+            vertex.pdf_fwd = path[prev].convert_density(pdf, &vertex);
+
+            match hit.material.scatter(&ray, &hit, sampler) {
+                None => {
+                    vertex.delta = true;
+                    path.push(vertex);
+                    break;
+                }
+                Some(ReflectionRecord::Specular {
+                    specular_ray,
+                    attenuation,
+                }) => {
+                    vertex.delta = true;
+                    beta *= attenuation;
+                    pdf = 0.0;
+                    ray = specular_ray;
+                }
+                Some(ReflectionRecord::Scatter {
+                    pdf: scattering_pdf,
+                    attenuation,
+                }) => {
+                    let scattered = Ray::new(hit.p, scattering_pdf.generate(sampler), ray.time);
+                    pdf = scattering_pdf.value(scattered.dir);
+                    if pdf <= 0.0 {
+                        path.push(vertex);
+                        break;
+                    }
+
+                    beta *= attenuation * hit.material.scattering_pdf(&ray, &hit, &scattered) / pdf;
+
+                    let pdf_rev = scattering_pdf.value(-ray.dir);
+                    path[prev].pdf_rev = vertex.convert_density(pdf_rev, &path[prev]);
+                    ray = scattered;
+                }
+            }
+            path.push(vertex);
+
+            if !russian_roulette(bounce, self.rr_depth, &mut beta, sampler) {
+                break;
+            }
+            bounce += 1;
+        }
+
+        Color::new_empty()
+    }
+
+    // Starts a light subpath on a random point of the lights list.
+    fn light_subpath<'a>(
+        &self,
+        scene: &SceneView<'a>,
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> Vec<Vertex<'a>> {
+        let mut path = vec![];
+
+        let sample = match sample_emission(scene, time, sampler) {
+            Some(sample) => sample,
+            None => return path,
+        };
+
+        let LightSample {
+            hit,
+            ray,
+            radiance,
+            pdf_pos,
+            pdf_dir,
+        } = sample;
+        path.push(Vertex::surface(hit, radiance / pdf_pos));
+        let beta = radiance * hit.normal.dot(ray.dir).abs() / (pdf_pos * pdf_dir);
+        self.random_walk(
+            scene,
+            ray,
+            beta,
+            pdf_dir,
+            &mut path,
+            self.max_depth as usize + 1,
+            sampler,
+        );
+
+        if path.len() > 1 {
+            path[0].pdf_fwd = origin_pdf(scene, &path[0], &path[1]);
+        }
+
+        path
+    }
+
+    // Contribution of the path made of the first `s` light and `t` camera vertices.
+    // Paths that reach the camera directly (t == 1) are splatted instead of returned.
+    #[allow(clippy::too_many_arguments)]
+    fn connect(
+        &self,
+        scene: &SceneView,
+        camera: &[Vertex],
+        light: &[Vertex],
+        (s, t): (usize, usize),
+        time: f32,
+        sampler: &mut dyn Sampler,
+        splats: &mut Vec<Splat>,
+    ) -> Color {
+        let pt = &camera[t - 1];
+        let mut sampled = None;
+        let mut splat = None;
+
+        let contribution = if s == 0 {
+            // the camera subpath found a light by itself
+            match &pt.hit {
+                Some(hit) => pt.beta * emission(hit, camera[t - 2].p, time),
+                None => Color::new_empty(),
+            }
+        } else if t == 1 {
+            let qs = &light[s - 1];
+            let hit = qs
+                .hit
+                .as_ref()
+                .expect("light subpath vertices are on surfaces");
+            if qs.delta || !visible(scene, qs.p, pt.p, time) {
+                return Color::new_empty();
+            }
+            splat = scene.camera.project(qs.p);
+            if splat.is_none() {
+                return Color::new_empty();
+            }
+
+            let to_camera = pt.p - qs.p;
+            let f = scattering(hit, light[s - 2].p, pt.p, time, sampler).0;
+            qs.beta * f * scene.camera.importance_pdf(-to_camera) / to_camera.length_squared()
+        } else if s == 1 {
+            // sample the lights list from the camera vertex, next event estimation
+            let hit = pt
+                .hit
+                .as_ref()
+                .expect("camera subpath vertices are on surfaces");
+            if pt.delta {
+                return Color::new_empty();
+            }
+
+            let to_light = Ray::new(pt.p, scene.lights.random(pt.p, sampler), time);
+            let light_pdf = scene.light_pdf(pt.p, to_light.dir);
+            if light_pdf <= 0.0 {
+                return Color::new_empty();
+            }
+            let light_hit = match scene.hit(&to_light, RayKind::Shadow) {
+                Some(light_hit) => light_hit,
+                None => return Color::new_empty(),
+            };
+
+            let emitted = light_hit.material.emitted(&to_light, &light_hit);
+            let mut vertex = Vertex::surface(light_hit, emitted / light_pdf);
+            vertex.pdf_fwd = origin_pdf(scene, &vertex, pt);
+            sampled = Some(vertex);
+
+            let f = scattering(hit, camera[t - 2].p, light_hit.p, time, sampler).0;
+            pt.beta * f * emitted / light_pdf
+        } else {
+            let qs = &light[s - 1];
+            if qs.delta || pt.delta {
+                return Color::new_empty();
+            }
+
+            let (qs_hit, pt_hit) = match (&qs.hit, &pt.hit) {
+                (Some(qs_hit), Some(pt_hit)) => (qs_hit, pt_hit),
+                _ => return Color::new_empty(),
+            };
+            let f_qs = scattering(qs_hit, light[s - 2].p, pt.p, time, sampler).0;
+            let f_pt = scattering(pt_hit, camera[t - 2].p, qs.p, time, sampler).0;
+            let contribution = qs.beta * f_qs * f_pt * pt.beta / (qs.p - pt.p).length_squared();
+
+            if contribution.near_zero() || !visible(scene, pt.p, qs.p, time) {
+                return Color::new_empty();
+            }
+            contribution
+        };
+
+        if contribution.near_zero() {
+            return Color::new_empty();
+        }
+
+        let weight = self.mis_weight(scene, camera, light, sampled, (s, t), time, sampler);
+        match splat {
+            Some((u, v)) => {
+                splats.push(Splat {
+                    s: u,
+                    t: v,
+                    color: weight * contribution,
+                    bounces: (s + t - 2) as u32,
+                });
+                Color::new_empty()
+            }
+            None => weight * contribution,
+        }
+    }
+
+    // Weight of the (s, t) path against every other (s', t') that builds the same vertices.
+    // Walks out from the connection along both subpaths, accumulating the ratio of the
+    // alternative strategy's density to this one's.
+    #[allow(clippy::too_many_arguments)]
+    fn mis_weight(
+        &self,
+        scene: &SceneView,
+        camera: &[Vertex],
+        light: &[Vertex],
+        sampled: Option<Vertex>,
+        (s, t): (usize, usize),
+        time: f32,
+        sampler: &mut dyn Sampler,
+    ) -> f32 {
+        if s + t == 2 {
+            return 1.0;
+        }
+
+        let mut camera = camera[..t].to_vec();
+        let mut light = match sampled {
+            Some(vertex) => vec![vertex],
+            None => light[..s].to_vec(),
+        };
+
+        // densities at and next to the connection, as if the path had been built from
+        // the other end
+        let pt_rev = match s {
+            0 => origin_pdf(scene, &camera[t - 1], &camera[t - 2]),
+            _ => light[s - 1].pdf(
+                scene,
+                s.checked_sub(2).map(|i| &light[i]),
+                &camera[t - 1],
+                time,
+                sampler,
+            ),
+        };
+        if s == 0 && pt_rev == 0.0 {
+            // an emitter missing from the lights list, no other strategy can find it
+            return 1.0;
+        }
+
+        let pt_minus_rev = match (s, t) {
+            (_, 1) => None,
+            (0, _) => {
+                let hit = camera[t - 1].hit.as_ref().expect("lights are surfaces");
+                Some(
+                    camera[t - 1]
+                        .convert_density(emission_pdf(hit, camera[t - 2].p), &camera[t - 2]),
+                )
+            }
+            _ => {
+                let prev = Some(&light[s - 1]);
+                Some(camera[t - 1].pdf(scene, prev, &camera[t - 2], time, sampler))
+            }
+        };
+        let qs_rev = match s {
+            0 => None,
+            _ => Some(camera[t - 1].pdf(
+                scene,
+                t.checked_sub(2).map(|i| &camera[i]),
+                &light[s - 1],
+                time,
+                sampler,
+            )),
+        };
+        let qs_minus_rev = match s {
+            0 | 1 => None,
+            _ => {
+                let prev = Some(&camera[t - 1]);
+                Some(light[s - 1].pdf(scene, prev, &light[s - 2], time, sampler))
+            }
+        };
+
+        camera[t - 1].pdf_rev = pt_rev;
+        camera[t - 1].delta = false;
+        if let Some(pdf) = pt_minus_rev {
+            camera[t - 2].pdf_rev = pdf;
+        }
+        if let Some(pdf) = qs_rev {
+            light[s - 1].pdf_rev = pdf;
+            light[s - 1].delta = false;
+        }
+        if let Some(pdf) = qs_minus_rev {
+            light[s - 2].pdf_rev = pdf;
+        }
+
+        // specular vertices have no density, they cancel out of the ratios
+        let remap = |pdf: f32| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera[i].pdf_rev) / remap(camera[i].pdf_fwd);
+            // i == 1 is the light subpath reaching the camera by itself
+            let possible = i > 1 || scene.camera.is_pinhole();
+            if possible && !camera[i].delta && !camera[i - 1].delta {
+                sum += self.heuristic.ratio(ratio);
+            }
+        }
+
+        let mut ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].pdf_rev) / remap(light[i].pdf_fwd);
+            let delta_before = i > 0 && light[i - 1].delta;
+            if !light[i].delta && !delta_before {
+                sum += self.heuristic.ratio(ratio);
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+}
+
+impl Integrator for BdptIntegrator {
+    fn li(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        splats: &mut Vec<Splat>,
+    ) -> Radiance {
+        let time = ray.time;
+        let max_depth = self.max_depth as usize;
+
+        let mut camera = vec![Vertex::camera(ray.orig)];
+        let pdf = if scene.camera.is_pinhole() {
+            scene.camera.importance_pdf(ray.dir)
+        } else {
+            0.0
+        };
+        let beta = Color::new(1.0, 1.0, 1.0);
+        // only the camera subpath can see the background
+        let background =
+            self.random_walk(scene, ray, beta, pdf, &mut camera, max_depth + 2, sampler);
+        let mut radiance = Radiance::default();
+        radiance.add(camera.len() as u32 - 1, background);
+
+        let light = self.light_subpath(scene, time, sampler);
+
+        for t in 1..=camera.len() {
+            // s == 1 samples its own light, it doesn't need the light subpath
+            for s in 0..=light.len().max(1) {
+                if (s == 1 && t == 1) || s + t < 2 || s + t - 2 > max_depth {
+                    continue;
+                }
+                if t == 1 && !scene.camera.is_pinhole() {
+                    continue;
+                }
+
+                let contribution =
+                    self.connect(scene, &camera, &light, (s, t), time, sampler, splats);
+                radiance.add((s + t - 2) as u32, contribution);
+            }
+        }
+
+        radiance
+    }
+}
+
+// Material response at `hit` for light going between `from` and `to`: the attenuation
+// times the cosine weighted scattering density, and the density of sampling `to`.
+fn scattering(
+    hit: &HitRecord,
+    from: Point3,
+    to: Point3,
+    time: f32,
+    sampler: &mut dyn Sampler,
+) -> (Color, f32) {
+    let ray = Ray::new(from, hit.p - from, time);
+    let mut hit = *hit;
+    hit.set_face_normal(&ray, hit.normal);
+
+    match hit.material.scatter(&ray, &hit, sampler) {
+        Some(ReflectionRecord::Scatter { pdf, attenuation }) => {
+            let scattered = Ray::new(hit.p, to - hit.p, time);
+            let f = attenuation * hit.material.scattering_pdf(&ray, &hit, &scattered);
+            (f, pdf.value(scattered.dir))
+        }
+        _ => (Color::new_empty(), 0.0),
+    }
+}
+
+// Area density of the light vertex `light` as next event estimation from `from` would
+// pick it. Used for the first vertex of light subpaths too, so the strategies starting on
+// a light and the ones sampling it from the camera side are weighed alike.
+fn origin_pdf(scene: &SceneView, light: &Vertex, from: &Vertex) -> f32 {
+    from.convert_density(scene.light_pdf(from.p, light.p - from.p), light)
+}
+
+fn visible(scene: &SceneView, from: Point3, to: Point3, time: f32) -> bool {
+    let ray = Ray::new(from, to - from, time);
+    stats::trace(scene.world, &ray, 0.999, RayKind::Shadow).is_none()
+}
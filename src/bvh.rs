@@ -1,6 +1,7 @@
 use crate::aabb::AABB;
 use crate::hittable::*;
 use crate::ray::Ray;
+use crate::stats;
 
 use std::cmp::Ordering;
 use std::sync::Arc;
@@ -12,7 +13,9 @@ enum BVHNode {
 
 pub struct BVH {
     tree: BVHNode,
-    bbox: AABB
+    bbox: AABB,
+    // object_count() of everything below, kept so the object debug view doesn't walk the tree
+    objects: usize
 }
 
 impl BVH {
@@ -57,7 +60,8 @@ impl BVH {
             1 => {
                 let leaf = hitable.pop().unwrap();
                 if let Some(bbox) = leaf.bounding_box(time0, time1) {
-                    BVH { tree: BVHNode::Leaf(leaf), bbox }
+                    let objects = leaf.object_count();
+                    BVH { tree: BVHNode::Leaf(leaf), bbox, objects }
                 } else {
                     panic!["no bounding box in bvh node"]
                 }
@@ -66,21 +70,23 @@ impl BVH {
                 let right = BVH::new(hitable.drain(len / 2..).collect(), time0, time1);
                 let left = BVH::new(hitable, time0, time1);
                 let bbox = AABB::surrounding_box(&left.bbox, &right.bbox);
-                BVH { tree: BVHNode::Branch { left: Arc::new(left), right: Arc::new(right) }, bbox }
+                let objects = left.objects + right.objects;
+                BVH { tree: BVHNode::Branch { left: Arc::new(left), right: Arc::new(right) }, bbox, objects }
             }
         }
     }
 }
 
 impl Hittable for BVH {
-    fn hit(&self, r: &Ray, t_min: f32, mut t_max: f32) -> Option<HitRecord> {
-        if self.bbox.hit(&r, t_min, t_max) {
+    fn hit(&self, r: &Ray, t_min: f32, mut t_max: f32) -> Option<HitRecord<'_>> {
+        stats::node_visit();
+        if self.bbox.hit(r, t_min, t_max) {
             match &self.tree {
-                BVHNode::Leaf(leaf) => leaf.hit(&r, t_min, t_max),
+                BVHNode::Leaf(leaf) => leaf.hit(r, t_min, t_max),
                 BVHNode::Branch { left, right} => {
-                    let left = left.hit(&r, t_min, t_max);
+                    let left = left.hit(r, t_min, t_max);
                     if let Some(l) = &left { t_max = l.t };
-                    let right = right.hit(&r, t_min, t_max);
+                    let right = right.hit(r, t_min, t_max);
                     if right.is_some() { right } else { left }
                 }
             }
@@ -92,4 +98,26 @@ impl Hittable for BVH {
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         Some(self.bbox.clone())
     }
+
+    fn object_count(&self) -> usize {
+        self.objects
+    }
+
+    // the right branch numbers its objects after the left one's
+    fn hit_object(&self, r: &Ray, t_min: f32, mut t_max: f32) -> Option<(HitRecord<'_>, usize)> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+        match &self.tree {
+            BVHNode::Leaf(leaf) => leaf.hit_object(r, t_min, t_max),
+            BVHNode::Branch { left, right } => {
+                let left_hit = left.hit_object(r, t_min, t_max);
+                if let Some((l, _)) = &left_hit { t_max = l.t };
+                match right.hit_object(r, t_min, t_max) {
+                    Some((hit, index)) => Some((hit, left.objects + index)),
+                    None => left_hit,
+                }
+            }
+        }
+    }
 }
@@ -1,75 +1,1187 @@
+use crate::lens::*;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::*;
-use rand::prelude::*;
 
-pub struct Camera {
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
+use std::path::Path;
+
+// Turns image plane coordinates into camera rays. (s, t) run from 0 to 1 across the image,
+// starting at its bottom left corner.
+pub trait Camera: Send + Sync {
+    // The ray through (s, t) and what its radiance is weighed by, None where the camera
+    // sees nothing, like the corners of a circular fisheye. The weight is 1 for ideal
+    // cameras, real lenses let less light through towards the corners. Every camera takes
+    // a 2D lens sample and then a 1D time sample, needed or not, so the dimensions the
+    // integrator gets don't depend on the camera.
+    fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Option<(Ray, f32)>;
+
+    // when the shutter opens and closes
+    fn shutter(&self) -> (f32, f32);
+
+    // how it lets light in meanwhile, for anything else that needs times spread like the
+    // camera rays', such as photons
+    fn shutter_curve(&self) -> ShutterCurve;
+
+    // What the film multiplies radiance by, 1 unless the camera was given an exposure.
+    fn exposure(&self) -> f32 {
+        1.0
+    }
+
+    // Moves an animated camera to `frame`, before the frame is rendered.
+    fn set_frame(&mut self, _frame: usize) {}
+
+    // how a stereo camera packs its two eyes into the image
+    fn stereo_layout(&self) -> Option<StereoLayout> {
+        None
+    }
+
+    // Whether every ray leaves from a single point, so rays from the scene can hit the
+    // camera. Only those cameras need project() and importance_pdf().
+    fn is_pinhole(&self) -> bool {
+        false
+    }
+
+    // Image plane coordinates (s, t) that get_ray() would take to see `p`, or None if it
+    // can't. They can be outside [0, 1] for points off the image.
+    fn project(&self, _p: Point3) -> Option<(f32, f32)> {
+        None
+    }
+
+    // Solid angle density of a camera ray leaving in `dir`, with the rays spread evenly
+    // over the image plane. Light subpaths connecting to the camera are weighed by it.
+    fn importance_pdf(&self, _dir: Vec3) -> f32 {
+        0.0
+    }
+}
+
+// A 35 mm full frame sensor is 24 mm tall, the thin lens works out its focal length from
+// it, with the scene in metres.
+const SENSOR_HEIGHT: f32 = 0.024;
+
+// Photographic exposure, for scenes whose lights are given in physical units (cd/m^2 for
+// radiance). It scales radiance so what would just saturate a sensor of this ISO comes
+// out as 1, the saturation based exposure of Lagarde and de Rousiers' "Moving Frostbite
+// to Physically Based Rendering".
+#[derive(Debug, Clone, Copy)]
+pub struct Exposure {
+    pub iso: f32,
+    // Seconds from time0 to time1. A shutter curve that isn't fully open all that time
+    // lets in less light.
+    pub shutter_time: f32,
+    // For pinholes and cameras without an aperture, like the orthographic one. Lenses
+    // have the f-number their aperture gives them, see PerspectiveCamera::f_number().
+    pub f_number: f32,
+}
+
+impl Exposure {
+    pub fn new(iso: f32, shutter_time: f32, f_number: f32) -> Self {
+        Self {
+            iso,
+            shutter_time,
+            f_number,
+        }
+    }
+
+    // the exposure value at ISO 100, which photographers' tables go by
+    pub fn ev100(&self) -> f32 {
+        (self.f_number * self.f_number / self.shutter_time * 100.0 / self.iso).log2()
+    }
+
+    pub fn scale(&self) -> f32 {
+        1.0 / (1.2 * self.ev100().exp2())
+    }
+}
+
+// How the shutter lets light in over the time it is open.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShutterCurve {
+    // fully open the whole time
+    Uniform,
+    // opening over the first `opening` of the time and closing over the last `closing`,
+    // like a mechanical shutter, so moving things blur out softly instead of stopping dead
+    Trapezoid { opening: f32, closing: f32 },
+    // A sensor read out row by row, top to bottom, over `readout` of the time. Every row
+    // is exposed for the rest of it, so fast moves lean.
+    Rolling { readout: f32 },
+}
+
+impl ShutterCurve {
+    // `opening` and `closing` are shares of the shutter time and can't overlap
+    pub fn trapezoid(opening: f32, closing: f32) -> Result<Self, String> {
+        if !(opening >= 0.0 && closing >= 0.0 && opening + closing <= 1.0) {
+            return Err(format!(
+                "the shutter can't open over {} and close over {} of its time",
+                opening, closing
+            ));
+        }
+        Ok(ShutterCurve::Trapezoid { opening, closing })
+    }
+
+    // the last row has to start before the shutter closes
+    pub fn rolling(readout: f32) -> Result<Self, String> {
+        if !(0.0..1.0).contains(&readout) {
+            return Err(format!("a readout of {} leaves no time to expose", readout));
+        }
+        Ok(ShutterCurve::Rolling { readout })
+    }
+
+    // the share of the shutter time's light a point of the image gets
+    pub fn open_area(&self) -> f32 {
+        match *self {
+            ShutterCurve::Uniform => 1.0,
+            ShutterCurve::Trapezoid { opening, closing } => 1.0 - (opening + closing) / 2.0,
+            ShutterCurve::Rolling { readout } => 1.0 - readout,
+        }
+    }
+
+    // How far through the shutter time a sample `u` lands for image row `t`, with the
+    // samples spread like the light the curve lets in.
+    pub fn sample(&self, u: f32, t: f32) -> f32 {
+        match *self {
+            ShutterCurve::Uniform => u,
+            ShutterCurve::Trapezoid { opening, closing } => {
+                // invert the running area under the curve, ramp up, flat top, ramp down
+                let area = self.open_area();
+                let a = u * area;
+                if a < opening / 2.0 {
+                    (2.0 * opening * a).sqrt()
+                } else if a < area - closing / 2.0 {
+                    opening + (a - opening / 2.0)
+                } else {
+                    1.0 - (2.0 * closing * (area - a)).max(0.0).sqrt()
+                }
+            }
+            ShutterCurve::Rolling { readout } => readout * (1.0 - t) + u * (1.0 - readout),
+        }
+    }
+}
+
+// Where a camera stands, which way it looks and when and how its shutter is open. `w`
+// points back from where it looks, `u` to the right of the image and `v` up.
+#[derive(Debug, Clone, Copy)]
+pub struct View {
     pub origin: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+    pub time0: f32,
+    pub time1: f32,
+    pub shutter_curve: ShutterCurve,
+    pub exposure: Option<Exposure>,
+}
+
+impl View {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, time0: f32, time1: f32) -> Self {
+        let w = (lookfrom - lookat).unit_vector();
+        let u = vup.cross(w).unit_vector();
+        let v = w.cross(u);
+
+        Self {
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            time0,
+            time1,
+            shutter_curve: ShutterCurve::Uniform,
+            exposure: None,
+        }
+    }
+
+    // the lens sample and the time a ray for image row `t` leaves at
+    fn sample(&self, t: f32, sampler: &mut dyn Sampler) -> ((f32, f32), f32) {
+        let lens = sampler.get_2d();
+        let when = self.shutter_curve.sample(sampler.get_1d(), t);
+        let time = self.time0 + when * (self.time1 - self.time0);
+        (lens, time)
+    }
+
+    fn exposure(&self) -> f32 {
+        self.exposure.map_or(1.0, |e| self.exposure_at(e.f_number))
+    }
+
+    // the same for a lens at `f_number`, whatever the exposure says
+    fn exposure_at(&self, f_number: f32) -> f32 {
+        self.exposure.map_or(1.0, |e| {
+            Exposure { f_number, ..e }.scale() * self.shutter_curve.open_area()
+        })
+    }
+
+    // a direction given in camera space, x right, y up and z back, into the world
+    fn world_dir(&self, d: Vec3) -> Vec3 {
+        d.x * self.u + d.y * self.v + d.z * self.w
+    }
+
+    fn camera_dir(&self, d: Vec3) -> Vec3 {
+        Vec3::new(d.dot(self.u), d.dot(self.v), d.dot(self.w))
+    }
+}
+
+// The outline of a thin lens aperture, which out of focus highlights take on.
+#[derive(Clone)]
+pub enum ApertureShape {
+    Disk,
+    // a regular polygon with a corner for each blade, turned `rotation` degrees
+    Polygon { blades: u32, rotation: f32 },
+    Mask(ApertureMask),
+}
+
+impl ApertureShape {
+    // a point on the aperture, within the unit disk or square
+    fn sample(&self, u: (f32, f32)) -> (f32, f32) {
+        match self {
+            ApertureShape::Disk => {
+                let p = Vec3::sample_unit_disk(u);
+                (p.x, p.y)
+            }
+            ApertureShape::Polygon { blades, rotation } => {
+                // pick one of the triangles fanning out from the middle, then a point on it
+                let n = (*blades).max(3) as f32;
+                let side = (u.0 * n).floor().min(n - 1.0);
+                let along = u.0 * n - side;
+                let corner = |i: f32| {
+                    let angle = rotation.to_radians() + 2.0 * PI * i / n;
+                    (angle.cos(), angle.sin())
+                };
+                let (a, b) = (corner(side), corner(side + 1.0));
+                let r = along.sqrt();
+                (
+                    r * ((1.0 - u.1) * a.0 + u.1 * b.0),
+                    r * ((1.0 - u.1) * a.1 + u.1 * b.1),
+                )
+            }
+            ApertureShape::Mask(mask) => mask.sample(u),
+        }
+    }
+}
+
+// An aperture drawn as an image, light gets through in proportion to how bright it is.
+// The image is fitted into the square around the unit disk.
+#[derive(Clone)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    // running sums of brightness along each row, then down the row totals
+    row_cdfs: Vec<f32>,
+    column_cdf: Vec<f32>,
+}
+
+impl ApertureMask {
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .to_rgb();
+        let (width, height) = image.dimensions();
+        let (width, height) = (width as usize, height as usize);
+
+        let mut row_cdfs = Vec::with_capacity(width * height);
+        let mut column_cdf = Vec::with_capacity(height);
+        let mut total = 0.0;
+        for y in 0..height {
+            let mut sum = 0.0;
+            for x in 0..width {
+                let p = image.get_pixel(x as u32, y as u32);
+                sum += (p[0] as f32 + p[1] as f32 + p[2] as f32) / (3.0 * 255.0);
+                row_cdfs.push(sum);
+            }
+            total += sum;
+            column_cdf.push(total);
+        }
+        if total <= 0.0 {
+            return Err(format!("{}: the aperture mask is black", path.display()));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            row_cdfs,
+            column_cdf,
+        })
+    }
+
+    fn sample(&self, u: (f32, f32)) -> (f32, f32) {
+        // the row by its share of the whole image, then the pixel by its share of the row,
+        // and what is left of each number places the point within the pixel
+        let (y, fy) = pick(&self.column_cdf, u.0);
+        let (x, fx) = pick(&self.row_cdfs[y * self.width..(y + 1) * self.width], u.1);
+
+        let size = self.width.max(self.height) as f32;
+        let px = (x as f32 + fx - self.width as f32 / 2.0) / size * 2.0;
+        let py = (self.height as f32 / 2.0 - y as f32 - fy) / size * 2.0;
+        (px, py)
+    }
+}
+
+// The bin that `u` of the way along the running sum `cdf` lands in, and how far into it.
+fn pick(cdf: &[f32], u: f32) -> (usize, f32) {
+    let total = cdf[cdf.len() - 1];
+    let target = u * total;
+    let i = cdf.partition_point(|&c| c <= target).min(cdf.len() - 1);
+    let start = if i > 0 { cdf[i - 1] } else { 0.0 };
+    let width = cdf[i] - start;
+    let f = if width > 0.0 {
+        ((target - start) / width).clamp(0.0, 1.0)
+    } else {
+        0.5
+    };
+    (i, f)
+}
+
+// The thin lens perspective camera. With no aperture it is a pinhole.
+pub struct PerspectiveCamera {
+    pub view: View,
     pub lower_left_corner: Point3,
     pub horizontal: Vec3,
     pub vertical: Vec3,
-    pub w: Vec3,
-    pub v: Vec3,
-    pub u: Vec3,
     pub lens_radius: f32,
-    pub time0: f32,
-    pub time1: f32,
+    pub aperture_shape: ApertureShape,
+    // Anamorphic lenses squeeze the aperture sideways, so out of focus highlights come
+    // out this many times taller than wide.
+    pub squeeze: f32,
+    pub focus_dist: f32,
+    // normal of the plane things are sharp on when it is tilted, None when it faces the
+    // camera
+    pub focus_normal: Option<Vec3>,
+}
+
+impl PerspectiveCamera {
+    // `vfov` is the vertical field of view in degrees, things `focus_dist` away are sharp
+    pub fn new(view: View, vfov: f32, aspect_ratio: f32, aperture: f32, focus_dist: f32) -> Self {
+        let theta = vfov.to_radians();
+        let h = (theta / 2.0).tan();
+
+        let viewport_height = 2.0 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let horizontal = focus_dist * viewport_width * view.u;
+        let vertical = focus_dist * viewport_height * view.v;
+        let lower_left_corner =
+            view.origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * view.w;
+
+        Self {
+            view,
+            lower_left_corner,
+            horizontal,
+            vertical,
+            lens_radius: aperture / 2.0,
+            aperture_shape: ApertureShape::Disk,
+            squeeze: 1.0,
+            focus_dist,
+            focus_normal: None,
+        }
+    }
+
+    // Tilts the plane of focus `down` degrees about the horizontal axis of the image, top
+    // edge away from the camera, and `right` degrees about the vertical one, right edge
+    // away. It still goes through the point straight ahead that was in focus. Tilting it
+    // steeply against the ground keeps only a band of the scene sharp, which makes it
+    // look like a miniature.
+    pub fn tilt(&mut self, down: f32, right: f32) {
+        let w = self.view.w;
+        let w = rotate(w, self.view.u, -down.to_radians());
+        let w = rotate(w, self.view.v, right.to_radians());
+        self.focus_normal = Some(w);
+    }
+
+    // Slides the image across the film by fractions of its width and height without
+    // turning the camera, like the shift of a view camera keeping verticals upright.
+    pub fn shift(&mut self, right: f32, up: f32) {
+        self.lower_left_corner += right * self.horizontal + up * self.vertical;
+    }
+
+    // the focal length that gives the field of view on a full frame sensor
+    pub fn focal_length(&self) -> f32 {
+        SENSOR_HEIGHT * self.focus_dist / self.vertical.length()
+    }
+
+    // None for a pinhole, which has no aperture to meter by
+    pub fn f_number(&self) -> Option<f32> {
+        (self.lens_radius > 0.0).then(|| self.focal_length() / (2.0 * self.lens_radius))
+    }
+
+    // opens or stops down the aperture, which changes the depth of field too
+    pub fn set_f_number(&mut self, f_number: f32) {
+        self.lens_radius = self.focal_length() / f_number / 2.0;
+    }
+
+    fn plane_distance(&self) -> f32 {
+        -(self.lower_left_corner - self.view.origin).dot(self.view.w)
+    }
+
+    // the ray through (s, t) from the point `lens` picks on an aperture of `shape`
+    fn ray(&self, s: f32, t: f32, shape: &ApertureShape, lens: (f32, f32), time: f32) -> Ray {
+        let target = self.lower_left_corner + s * self.horizontal + t * self.vertical;
+        if self.lens_radius == 0.0 {
+            let origin = self.view.origin;
+            return Ray::new(origin, target - origin, time);
+        }
+
+        let (x, y) = shape.sample(lens);
+        let (x, y) = (self.lens_radius * x / self.squeeze, self.lens_radius * y);
+        let origin = self.view.origin + self.view.u * x + self.view.v * y;
+
+        // where the ray through the middle of the lens meets the plane of focus, a tilted
+        // plane it never reaches is in focus at infinity
+        let focus = match self.focus_normal {
+            None => target,
+            Some(normal) => {
+                let dir = target - self.view.origin;
+                let along = dir.dot(normal);
+                if along >= 0.0 {
+                    return Ray::new(origin, dir, time);
+                }
+                self.view.origin + dir * (-self.focus_dist * self.view.w.dot(normal) / along)
+            }
+        };
+
+        Ray::new(origin, focus - origin, time)
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Option<(Ray, f32)> {
+        let (lens, time) = self.view.sample(t, sampler);
+        Some((self.ray(s, t, &self.aperture_shape, lens, time), 1.0))
+    }
+
+    fn shutter(&self) -> (f32, f32) {
+        (self.view.time0, self.view.time1)
+    }
+
+    fn shutter_curve(&self) -> ShutterCurve {
+        self.view.shutter_curve
+    }
+
+    fn exposure(&self) -> f32 {
+        match self.f_number() {
+            Some(f_number) => self.view.exposure_at(f_number),
+            None => self.view.exposure(),
+        }
+    }
+
+    // the thin lens would need its aperture sampled
+    fn is_pinhole(&self) -> bool {
+        self.lens_radius == 0.0
+    }
+
+    fn project(&self, p: Point3) -> Option<(f32, f32)> {
+        let dir = p - self.view.origin;
+        let depth = -dir.dot(self.view.w);
+        if depth <= 0.0 {
+            return None;
+        }
+
+        let on_plane =
+            self.view.origin + dir * (self.plane_distance() / depth) - self.lower_left_corner;
+        let s = on_plane.dot(self.horizontal) / self.horizontal.length_squared();
+        let t = on_plane.dot(self.vertical) / self.vertical.length_squared();
+
+        Some((s, t))
+    }
+
+    fn importance_pdf(&self, dir: Vec3) -> f32 {
+        let cos = -dir.unit_vector().dot(self.view.w);
+        if cos <= 0.0 {
+            return 0.0;
+        }
+
+        // image plane area at unit distance
+        let area =
+            self.horizontal.length() * self.vertical.length() / self.plane_distance().powi(2);
+        1.0 / (area * cos.powi(3))
+    }
+}
+
+// Parallel rays from a rectangle around the view origin, so sizes don't shrink with
+// distance. For technical views, things behind the origin aren't seen.
+pub struct OrthographicCamera {
+    pub view: View,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl OrthographicCamera {
+    // `height` is how much of the scene the image spans vertically, in scene units
+    pub fn new(view: View, height: f32, aspect_ratio: f32) -> Self {
+        Self {
+            view,
+            width: height * aspect_ratio,
+            height,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Option<(Ray, f32)> {
+        let (_, time) = self.view.sample(t, sampler);
+        let offset = Vec3::new((s - 0.5) * self.width, (t - 0.5) * self.height, 0.0);
+        let origin = self.view.origin + self.view.world_dir(offset);
+
+        Some((Ray::new(origin, -self.view.w, time), 1.0))
+    }
+
+    fn shutter(&self) -> (f32, f32) {
+        (self.view.time0, self.view.time1)
+    }
+
+    fn shutter_curve(&self) -> ShutterCurve {
+        self.view.shutter_curve
+    }
+
+    fn exposure(&self) -> f32 {
+        self.view.exposure()
+    }
 }
 
-impl Camera {
+// Every direction around the camera, longitude across the image and latitude up it, for
+// environment captures. The image should be twice as wide as it is high.
+pub struct EquirectangularCamera {
+    pub view: View,
+    // For omni-directional stereo, how far right of the view origin the eye is when
+    // looking ahead. The eye circles the origin as the view turns, moving back in towards
+    // it near the poles so the two eyes meet there instead of swapping places.
+    pub eye: f32,
+}
+
+impl EquirectangularCamera {
+    pub fn new(view: View) -> Self {
+        Self { view, eye: 0.0 }
+    }
+
+    // one eye of an omni-directional stereo pair, `eye` right of the middle, negative for
+    // the left eye
+    pub fn ods(view: View, eye: f32) -> Self {
+        Self { view, eye }
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Option<(Ray, f32)> {
+        let (_, time) = self.view.sample(t, sampler);
+        // the middle of the image looks ahead
+        let phi = 2.0 * PI * (s - 0.5);
+        let theta = PI * (t - 0.5);
+        let dir = Vec3::new(
+            theta.cos() * phi.sin(),
+            theta.sin(),
+            -theta.cos() * phi.cos(),
+        );
+
+        let origin = if self.eye == 0.0 {
+            self.view.origin
+        } else {
+            let right = Vec3::new(phi.cos(), 0.0, phi.sin());
+            self.view.origin + self.view.world_dir(self.eye * theta.cos() * right)
+        };
+
+        Some((Ray::new(origin, self.view.world_dir(dir), time), 1.0))
+    }
+
+    fn shutter(&self) -> (f32, f32) {
+        (self.view.time0, self.view.time1)
+    }
+
+    fn shutter_curve(&self) -> ShutterCurve {
+        self.view.shutter_curve
+    }
+
+    fn exposure(&self) -> f32 {
+        self.view.exposure()
+    }
+
+    // an eye off the middle sees from a different point in every direction
+    fn is_pinhole(&self) -> bool {
+        self.eye == 0.0
+    }
+
+    fn project(&self, p: Point3) -> Option<(f32, f32)> {
+        let d = self.view.camera_dir(p - self.view.origin);
+        let length = d.length();
+        if length == 0.0 {
+            return None;
+        }
+
+        let phi = d.x.atan2(-d.z);
+        let theta = (d.y / length).clamp(-1.0, 1.0).asin();
+        Some((phi / (2.0 * PI) + 0.5, theta / PI + 0.5))
+    }
+
+    // the image plane is 2 pi by pi radians and rows shrink by cos(theta) towards the poles
+    fn importance_pdf(&self, dir: Vec3) -> f32 {
+        let cos_theta = (1.0 - dir.unit_vector().dot(self.view.v).powi(2))
+            .max(0.0)
+            .sqrt();
+        if cos_theta == 0.0 {
+            return 0.0;
+        }
+
+        1.0 / (2.0 * PI * PI * cos_theta)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    // left eye on the left half of the image, right eye on the right
+    SideBySide,
+    // left eye on the top half
+    TopBottom,
+}
+
+impl StereoLayout {
+    // the aspect ratio of each eye's half of an image with `aspect_ratio`
+    pub fn eye_aspect(&self, aspect_ratio: f32) -> f32 {
+        match self {
+            StereoLayout::SideBySide => aspect_ratio / 2.0,
+            StereoLayout::TopBottom => aspect_ratio * 2.0,
+        }
+    }
+
+    // Which eye (s, t) falls on, true for the right one, and where it is on that eye's
+    // image.
+    fn split(&self, s: f32, t: f32) -> (bool, f32, f32) {
+        match self {
+            StereoLayout::SideBySide if s < 0.5 => (false, 2.0 * s, t),
+            StereoLayout::SideBySide => (true, 2.0 * s - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => (false, s, 2.0 * t - 1.0),
+            StereoLayout::TopBottom => (true, s, 2.0 * t),
+        }
+    }
+
+    // The pixels of one eye in an image `width` by `height`, as the column and row of its
+    // top left corner and its size.
+    pub fn eye_rect(&self, right: bool, width: usize, height: usize) -> [usize; 4] {
+        match (self, right) {
+            (StereoLayout::SideBySide, false) => [0, 0, width / 2, height],
+            (StereoLayout::SideBySide, true) => [width - width / 2, 0, width / 2, height],
+            (StereoLayout::TopBottom, false) => [0, 0, width, height / 2],
+            (StereoLayout::TopBottom, true) => [0, height - height / 2, width, height / 2],
+        }
+    }
+}
+
+// Two cameras rendered into one image, one for each eye.
+pub struct StereoCamera {
+    pub left: Box<dyn Camera>,
+    pub right: Box<dyn Camera>,
+    pub layout: StereoLayout,
+}
+
+impl StereoCamera {
+    pub fn new(left: Box<dyn Camera>, right: Box<dyn Camera>, layout: StereoLayout) -> Self {
+        Self {
+            left,
+            right,
+            layout,
+        }
+    }
+
+    // A pinhole eye either side of the view origin, `interocular` apart and looking the
+    // same way. Their images are shifted so things `convergence` away line up in both
+    // and sit at the depth of the screen, nearer things come out of it. `aspect_ratio` is
+    // the whole image's.
+    pub fn perspective(
+        view: View,
+        vfov: f32,
+        aspect_ratio: f32,
+        interocular: f32,
+        convergence: f32,
+        layout: StereoLayout,
+    ) -> Self {
+        let eye = |offset: f32| -> Box<dyn Camera> {
+            let view = View {
+                origin: view.origin + offset * view.u,
+                ..view
+            };
+            let aspect = layout.eye_aspect(aspect_ratio);
+            let mut cam = PerspectiveCamera::new(view, vfov, aspect, 0.0, convergence);
+            // at the convergence distance the image is as wide as `horizontal`
+            cam.shift(-offset / cam.horizontal.length(), 0.0);
+            Box::new(cam)
+        };
+
+        Self::new(eye(-interocular / 2.0), eye(interocular / 2.0), layout)
+    }
+
+    // Omni-directional stereo: a panorama for each eye that keeps the eyes `interocular`
+    // apart whichever way the viewer turns, for VR headsets. Each eye wants a 2:1 image,
+    // so top-bottom makes a square one.
+    pub fn ods(view: View, interocular: f32, layout: StereoLayout) -> Self {
+        let left = EquirectangularCamera::ods(view, -interocular / 2.0);
+        let right = EquirectangularCamera::ods(view, interocular / 2.0);
+        Self::new(Box::new(left), Box::new(right), layout)
+    }
+}
+
+impl Camera for StereoCamera {
+    fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Option<(Ray, f32)> {
+        let (right, s, t) = self.layout.split(s, t);
+        if right {
+            self.right.get_ray(s, t, sampler)
+        } else {
+            self.left.get_ray(s, t, sampler)
+        }
+    }
+
+    fn shutter(&self) -> (f32, f32) {
+        self.left.shutter()
+    }
+
+    fn shutter_curve(&self) -> ShutterCurve {
+        self.left.shutter_curve()
+    }
+
+    fn exposure(&self) -> f32 {
+        self.left.exposure()
+    }
+
+    fn set_frame(&mut self, frame: usize) {
+        self.left.set_frame(frame);
+        self.right.set_frame(frame);
+    }
+
+    fn stereo_layout(&self) -> Option<StereoLayout> {
+        Some(self.layout)
+    }
+}
+
+// Angular fisheye: the angle off the view axis grows evenly with the distance from the
+// middle of the image, up to half of `fov` at the edge of the circle that fits its height.
+// Rays outside that circle see nothing.
+pub struct FisheyeCamera {
+    pub view: View,
+    // half the field of view in radians
+    pub max_angle: f32,
+    pub aspect_ratio: f32,
+}
+
+impl FisheyeCamera {
+    // `fov` is the field of view across the image circle in degrees, up to 360
+    pub fn new(view: View, fov: f32, aspect_ratio: f32) -> Self {
+        Self {
+            view,
+            max_angle: fov.to_radians() / 2.0,
+            aspect_ratio,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Option<(Ray, f32)> {
+        let (_, time) = self.view.sample(t, sampler);
+        let x = (2.0 * s - 1.0) * self.aspect_ratio;
+        let y = 2.0 * t - 1.0;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let angle = r * self.max_angle;
+        let azimuth = y.atan2(x);
+        let dir = Vec3::new(
+            angle.sin() * azimuth.cos(),
+            angle.sin() * azimuth.sin(),
+            -angle.cos(),
+        );
+
+        Some((
+            Ray::new(self.view.origin, self.view.world_dir(dir), time),
+            1.0,
+        ))
+    }
+
+    fn shutter(&self) -> (f32, f32) {
+        (self.view.time0, self.view.time1)
+    }
+
+    fn shutter_curve(&self) -> ShutterCurve {
+        self.view.shutter_curve
+    }
+
+    fn exposure(&self) -> f32 {
+        self.view.exposure()
+    }
+
+    fn is_pinhole(&self) -> bool {
+        true
+    }
+
+    fn project(&self, p: Point3) -> Option<(f32, f32)> {
+        let d = self.view.camera_dir(p - self.view.origin);
+        let length = d.length();
+        if length == 0.0 {
+            return None;
+        }
+
+        let angle = (-d.z / length).clamp(-1.0, 1.0).acos();
+        let r = angle / self.max_angle;
+        if r > 1.0 {
+            return None;
+        }
+
+        let azimuth = d.y.atan2(d.x);
+        let x = r * azimuth.cos();
+        let y = r * azimuth.sin();
+        Some(((x / self.aspect_ratio + 1.0) / 2.0, (y + 1.0) / 2.0))
+    }
+
+    // The image plane is 4 * aspect ratio in (x, y) and the solid angle of a ring at
+    // angle a is sin(a) / a times what the same ring on a flat plane would be.
+    fn importance_pdf(&self, dir: Vec3) -> f32 {
+        let cos = (-dir.unit_vector().dot(self.view.w)).clamp(-1.0, 1.0);
+        let angle = cos.acos();
+        if angle > self.max_angle {
+            return 0.0;
+        }
+
+        let sinc = if angle < 1e-4 {
+            1.0
+        } else {
+            angle.sin() / angle
+        };
+        if sinc <= 0.0 {
+            return 0.0;
+        }
+
+        1.0 / (4.0 * self.aspect_ratio * self.max_angle * self.max_angle * sinc)
+    }
+}
+
+// One pose of an animated camera. `time` counts frames and can fall between them.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f32,
+    pub focus_dist: f32,
+    pub aperture: f32,
+    // PerspectiveCamera's squeeze, and the degrees and fractions of the image it is tilted
+    // and shifted by
+    pub squeeze: f32,
+    pub tilt: (f32, f32),
+    pub shift: (f32, f32),
+}
+
+impl CameraKeyframe {
+    // a plain thin lens at `time`, with no squeeze, tilt or shift
     pub fn new(
+        time: f32,
         lookfrom: Point3,
         lookat: Point3,
         vup: Vec3,
         vfov: f32,
-        aspect_ratio: f32,
-        aperture: f32,
         focus_dist: f32,
-        time0: f32,
-        time1: f32,
+        aperture: f32,
     ) -> Self {
-        let theta = vfov.to_radians();
-        let h = (theta / 2.0).tan();
-
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
+        Self {
+            time,
+            lookfrom,
+            lookat,
+            vup,
+            vfov,
+            focus_dist,
+            aperture,
+            squeeze: 1.0,
+            tilt: (0.0, 0.0),
+            shift: (0.0, 0.0),
+        }
+    }
+}
 
-        let w = (lookfrom - lookat).unit_vector();
-        let u = (vup.cross(w)).unit_vector();
-        let v = w.cross(u);
+// the smallest field of view, focus distance and squeeze an animated camera takes on
+const MIN_LENS_VALUE: f32 = 0.001;
 
-        let origin = lookfrom;
-        let horizontal = focus_dist * viewport_width * u;
-        let vertical = focus_dist * viewport_height * v;
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
+// A thin lens camera following Catmull-Rom splines through its keyframes, holding still
+// before the first and after the last. It keeps moving while the shutter is open, so
+// fast moves blur. Without an aperture it still isn't a pinhole: rays leave from wherever
+// the camera was at their time, and project() has no time to go by.
+pub struct AnimatedCamera {
+    pub keys: Vec<CameraKeyframe>,
+    pub aspect_ratio: f32,
+    // the aperture's outline, the keyframes only change its size
+    pub aperture_shape: ApertureShape,
+    // when the shutter opens and closes within a frame, 0 to 0.5 is a 180 degree shutter
+    pub time0: f32,
+    pub time1: f32,
+    pub shutter_curve: ShutterCurve,
+    // the f-number comes from the keyframes' aperture at the start of each frame, or from
+    // the exposure while they have none
+    pub exposure: Option<Exposure>,
+    pub frame: usize,
+    // the pose for the whole frame when the shutter opens for an instant, set by
+    // set_frame() so rays don't each evaluate the splines
+    still: Option<PerspectiveCamera>,
+}
 
-        let lens_radius = aperture / 2.0;
+impl AnimatedCamera {
+    pub fn new(
+        mut keys: Vec<CameraKeyframe>,
+        aspect_ratio: f32,
+        time0: f32,
+        time1: f32,
+    ) -> Result<Self, String> {
+        if keys.is_empty() {
+            return Err("an animated camera needs a keyframe".to_string());
+        }
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        if let Some(k) = keys.windows(2).find(|k| k[0].time >= k[1].time) {
+            return Err(format!("two camera keyframes at time {}", k[1].time));
+        }
+        if let Some(k) = keys
+            .iter()
+            .find(|k| !(k.vfov > 0.0 && k.vfov < 180.0 && k.focus_dist > 0.0))
+        {
+            return Err(format!(
+                "the camera keyframe at time {} has a field of view of {} and focuses {} away",
+                k.time, k.vfov, k.focus_dist
+            ));
+        }
 
-        Self {
-            origin,
-            horizontal,
-            vertical,
-            lower_left_corner,
-            w,
-            v,
-            u,
-            lens_radius,
+        let mut camera = Self {
+            keys,
+            aspect_ratio,
+            aperture_shape: ApertureShape::Disk,
             time0,
             time1,
+            shutter_curve: ShutterCurve::Uniform,
+            exposure: None,
+            frame: 0,
+            still: None,
+        };
+        camera.set_frame(0);
+        Ok(camera)
+    }
+
+    // the camera as it is `time` frames into the animation
+    pub fn at(&self, time: f32) -> PerspectiveCamera {
+        let mut camera = self.pose(time);
+        camera.aperture_shape = self.aperture_shape.clone();
+        camera
+    }
+
+    // at() with a disk for an aperture, get_ray() passes the shape on rather than copy it
+    fn pose(&self, time: f32) -> PerspectiveCamera {
+        let keys = &self.keys;
+        let mut view = View::new(
+            spline(keys, time, |k| k.lookfrom),
+            spline(keys, time, |k| k.lookat),
+            spline(keys, time, |k| k.vup),
+            self.time0,
+            self.time1,
+        );
+        view.shutter_curve = self.shutter_curve;
+        view.exposure = self.exposure;
+
+        // the splines overshoot between steep keys, keep the lens from turning inside out
+        let mut camera = PerspectiveCamera::new(
+            view,
+            spline(keys, time, |k| k.vfov).clamp(MIN_LENS_VALUE, 180.0 - MIN_LENS_VALUE),
+            self.aspect_ratio,
+            spline(keys, time, |k| k.aperture).max(0.0),
+            spline(keys, time, |k| k.focus_dist).max(MIN_LENS_VALUE),
+        );
+        camera.squeeze = spline(keys, time, |k| k.squeeze).max(MIN_LENS_VALUE);
+        let (down, right) = (
+            spline(keys, time, |k| k.tilt.0),
+            spline(keys, time, |k| k.tilt.1),
+        );
+        if down != 0.0 || right != 0.0 {
+            camera.tilt(down, right);
         }
+        camera.shift(
+            spline(keys, time, |k| k.shift.0),
+            spline(keys, time, |k| k.shift.1),
+        );
+        camera
     }
+}
+
+impl Camera for AnimatedCamera {
+    fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Option<(Ray, f32)> {
+        let lens = sampler.get_2d();
+        let when = self.shutter_curve.sample(sampler.get_1d(), t);
+        let time = self.time0 + when * (self.time1 - self.time0);
+        let moving;
+        let camera = match &self.still {
+            Some(camera) => camera,
+            None => {
+                moving = self.pose(self.frame as f32 + time);
+                &moving
+            }
+        };
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let origin = if self.lens_radius == 0.0 {
-            self.origin
+        Some((camera.ray(s, t, &self.aperture_shape, lens, time), 1.0))
+    }
+
+    fn shutter(&self) -> (f32, f32) {
+        (self.time0, self.time1)
+    }
+
+    fn shutter_curve(&self) -> ShutterCurve {
+        self.shutter_curve
+    }
+
+    fn exposure(&self) -> f32 {
+        self.pose(self.frame as f32 + self.time0).exposure()
+    }
+
+    fn set_frame(&mut self, frame: usize) {
+        self.frame = frame;
+        self.still = (self.time0 == self.time1).then(|| self.pose(frame as f32 + self.time0));
+    }
+}
+
+// The value `value` picks out of the keys, `time` along a Catmull-Rom spline through them.
+// Keys needn't be evenly spaced: the slope at each key is taken across its neighbours'
+// times, and at the ends from the one neighbour there is.
+fn spline<T>(keys: &[CameraKeyframe], time: f32, value: impl Fn(&CameraKeyframe) -> T) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let n = keys.len();
+    if n == 1 || time <= keys[0].time {
+        return value(&keys[0]);
+    }
+    if time >= keys[n - 1].time {
+        return value(&keys[n - 1]);
+    }
+
+    let slope = |i: usize| {
+        let (a, b) = (i.saturating_sub(1), (i + 1).min(n - 1));
+        (value(&keys[b]) - value(&keys[a])) * (1.0 / (keys[b].time - keys[a].time))
+    };
+
+    // keys[i] is the last one at or before `time`
+    let i = keys.partition_point(|k| k.time <= time) - 1;
+    let dt = keys[i + 1].time - keys[i].time;
+    let u = (time - keys[i].time) / dt;
+    let (u2, u3) = (u * u, u * u * u);
+
+    value(&keys[i]) * (2.0 * u3 - 3.0 * u2 + 1.0)
+        + slope(i) * ((u3 - 2.0 * u2 + u) * dt)
+        + value(&keys[i + 1]) * (3.0 * u2 - 2.0 * u3)
+        + slope(i + 1) * ((u3 - u2) * dt)
+}
+
+// A camera looking through a real lens, traced element by element, so the image vignettes,
+// distorts and breathes as it focuses the way the lens does. The view origin is the film,
+// the lens sits in front of it.
+pub struct RealisticCamera {
+    pub view: View,
+    pub lens: LensSystem,
+    pub film_width: f32,
+    pub film_height: f32,
+    // focal length over the diameter of the stop
+    pub f_number: f32,
+    // where rays get through the rear element, for film points further and further off
+    // the axis
+    pub pupil_bounds: Vec<PupilBounds>,
+}
+
+impl RealisticCamera {
+    // `film_diagonal` and `aperture` are in millimetres, 43.3 is a full frame sensor, and
+    // things `focus_dist` away from the film are sharp
+    pub fn new(
+        view: View,
+        mut lens: LensSystem,
+        film_diagonal: f32,
+        aspect_ratio: f32,
+        aperture: f32,
+        focus_dist: f32,
+    ) -> Result<Self, String> {
+        const PUPIL_BOUNDS: usize = 64;
+
+        lens.set_aperture(aperture);
+        lens.focus(focus_dist)?;
+        let (focal_length, _, _) = lens.thick_lens().ok_or("the lens has no focal length")?;
+        let f_number = focal_length / (2.0 * lens.stop_radius());
+
+        let diagonal = film_diagonal * 0.001;
+        let film_height = diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        let film_width = aspect_ratio * film_height;
+
+        let pupil_bounds = (0..PUPIL_BOUNDS)
+            .map(|i| {
+                let r0 = i as f32 / PUPIL_BOUNDS as f32 * diagonal / 2.0;
+                let r1 = (i + 1) as f32 / PUPIL_BOUNDS as f32 * diagonal / 2.0;
+                lens.exit_pupil_bounds(r0, r1)
+            })
+            .collect();
+
+        Ok(Self {
+            view,
+            lens,
+            film_width,
+            film_height,
+            f_number,
+            pupil_bounds,
+        })
+    }
+
+    fn film_radius(&self) -> f32 {
+        0.5 * (self.film_width * self.film_width + self.film_height * self.film_height).sqrt()
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Option<(Ray, f32)> {
+        let (lens, time) = self.view.sample(t, sampler);
+
+        // the lens flips the image, so the top right of the image is the bottom left of
+        // the film
+        let film = Point3::new(
+            -(s - 0.5) * self.film_width,
+            -(t - 0.5) * self.film_height,
+            0.0,
+        );
+        let r = (film.x * film.x + film.y * film.y).sqrt();
+        let n = self.pupil_bounds.len();
+        let bounds = self.pupil_bounds[((r / self.film_radius() * n as f32) as usize).min(n - 1)];
+        if bounds.area() == 0.0 {
+            return None;
+        }
+
+        // the bounds are for points along +x, turn them round to this one
+        let (x, y) = bounds.lerp(lens);
+        let (sin, cos) = if r > 0.0 {
+            (film.y / r, film.x / r)
         } else {
-            let rd = self.lens_radius * Vec3::random_in_unit_disk();
-            let offset = self.u * rd.x + self.v * rd.y;
-            self.origin + offset
+            (0.0, 1.0)
         };
+        let rear = Point3::new(cos * x - sin * y, sin * x + cos * y, self.lens.rear_z());
+
+        let dir = rear - film;
+        let out = self.lens.trace_from_film(&Ray::new(film, dir, time))?;
 
-        let time = self.time0 + rand::thread_rng().gen::<f32>() * (self.time1 - self.time0);
-        let dir = self.lower_left_corner + s * self.horizontal + t * self.vertical - origin;
+        // Light falls off with cos^4 of the angle to the film, and the bounds the ray
+        // came from stand for how much of the rear element lets light through. Scaled so
+        // the middle of the image at full aperture is about 1.
+        let cos_theta = -dir.z / dir.length();
+        let weight = cos_theta.powi(4) * bounds.area() / self.pupil_bounds[0].area();
 
-        Ray::new(origin, dir, time)
+        let orig = self.view.origin + self.view.world_dir(out.orig);
+        Some((Ray::new(orig, self.view.world_dir(out.dir), time), weight))
     }
+
+    fn shutter(&self) -> (f32, f32) {
+        (self.view.time0, self.view.time1)
+    }
+
+    fn shutter_curve(&self) -> ShutterCurve {
+        self.view.shutter_curve
+    }
+
+    // a real lens is at the f-number its stop makes it
+    fn exposure(&self) -> f32 {
+        self.view.exposure_at(self.f_number)
+    }
+}
+
+// `v` turned `angle` radians about the unit `axis`, the right hand way
+fn rotate(v: Vec3, axis: Vec3, angle: f32) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis.cross(v) * sin + axis * axis.dot(v) * (1.0 - cos)
 }
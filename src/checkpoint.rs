@@ -0,0 +1,353 @@
+use crate::aov::Aov;
+use crate::cli::Settings;
+use crate::debug::DebugView;
+use crate::film::Film;
+use crate::filter::{Filter, FilterKind};
+use crate::integrator::{IntegratorKind, MisHeuristic};
+use crate::sampler::{SamplerKind, Scramble};
+use crate::vec3::*;
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"REI-CKPT";
+const VERSION: u32 = 8;
+// longer than any name a checkpoint holds, so a corrupt length can't allocate much
+const MAX_STRING: u32 = 4096;
+
+// Everything needed to pick a render back up: which frame we were on, the index of the
+// next sample to take (samples are drawn in order, so with the seed this is the sampler
+// state) and the accumulated film. The integrator and its options, the sampler and the
+// pass size are kept so a resume can't mix different estimators into the same film.
+pub struct Checkpoint {
+    pub scene: String,
+    pub seed: u64,
+    pub integrator: IntegratorKind,
+    pub debug: Option<DebugView>,
+    pub mis_heuristic: MisHeuristic,
+    pub max_depth: u32,
+    pub rr_depth: u32,
+    pub photons: usize,
+    pub photon_radius: Option<f32>,
+    pub sampler: SamplerKind,
+    pub scramble: Scramble,
+    pub pass_samples: usize,
+    pub frame: usize,
+    pub next_sample: usize,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    // the start of `frame` rendered with `settings`
+    pub fn new(settings: &Settings, frame: usize) -> Self {
+        Self {
+            scene: settings.scene.clone(),
+            seed: settings.seed,
+            integrator: settings.integrator,
+            debug: settings.debug,
+            mis_heuristic: settings.mis_heuristic,
+            max_depth: settings.max_depth,
+            rr_depth: settings.rr_depth,
+            photons: settings.photons,
+            photon_radius: settings.photon_radius,
+            sampler: settings.sampler,
+            scramble: settings.scramble,
+            pass_samples: settings.pass_samples(),
+            frame,
+            next_sample: 0,
+            film: Film::new(
+                settings.width,
+                settings.height,
+                settings.filter(),
+                &settings.film_aovs(),
+            ),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        // write next to the old checkpoint and swap, so a crash mid-write never loses it
+        let tmp = path.with_extension("tmp");
+        {
+            let mut f = BufWriter::new(File::create(&tmp)?);
+            f.write_all(MAGIC)?;
+            write_u32(&mut f, VERSION)?;
+            write_u32(&mut f, self.scene.len() as u32)?;
+            f.write_all(self.scene.as_bytes())?;
+            f.write_all(&self.seed.to_le_bytes())?;
+            write_u32(&mut f, self.film.width as u32)?;
+            write_u32(&mut f, self.film.height as u32)?;
+            write_u32(&mut f, self.frame as u32)?;
+            write_u32(&mut f, self.next_sample as u32)?;
+            let filter = self.film.filter.kind.name();
+            write_u32(&mut f, filter.len() as u32)?;
+            f.write_all(filter.as_bytes())?;
+            f.write_all(&self.film.filter.radius.to_le_bytes())?;
+            for name in [
+                self.integrator.name(),
+                // empty for a beauty render
+                self.debug.map_or("", |view| view.name()),
+                self.mis_heuristic.name(),
+                self.sampler.name(),
+                self.scramble.name(),
+            ] {
+                write_u32(&mut f, name.len() as u32)?;
+                f.write_all(name.as_bytes())?;
+            }
+            write_u32(&mut f, self.max_depth)?;
+            write_u32(&mut f, self.rr_depth)?;
+            f.write_all(&(self.photons as u64).to_le_bytes())?;
+            // 0 for the radius worked out from the scene
+            f.write_all(&self.photon_radius.unwrap_or(0.0).to_le_bytes())?;
+            write_u32(&mut f, self.pass_samples as u32)?;
+            write_u32(&mut f, self.film.aovs.len() as u32)?;
+            for buffer in &self.film.aovs {
+                let name = buffer.aov.name();
+                write_u32(&mut f, name.len() as u32)?;
+                f.write_all(name.as_bytes())?;
+            }
+
+            for i in 0..self.film.width * self.film.height {
+                for v in self.film.sum[i].into_iter() {
+                    f.write_all(&v.to_le_bytes())?;
+                }
+                f.write_all(&self.film.sum_sq[i].to_le_bytes())?;
+                write_u32(&mut f, self.film.samples[i])?;
+                for v in self.film.filtered[i].into_iter() {
+                    f.write_all(&v.to_le_bytes())?;
+                }
+                f.write_all(&self.film.filter_weights[i].to_le_bytes())?;
+                for v in self.film.splat[i].into_iter() {
+                    f.write_all(&v.to_le_bytes())?;
+                }
+                for buffer in &self.film.aovs {
+                    for v in buffer.sum[i].into_iter().chain(buffer.splat[i]) {
+                        f.write_all(&v.to_le_bytes())?;
+                    }
+                }
+            }
+            f.flush()?;
+        }
+        fs::rename(tmp, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut f = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        f.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut f)? != VERSION {
+            return Err(invalid(format!("{} is not a checkpoint", path.display())));
+        }
+
+        let scene = read_string(&mut f)?;
+        let mut seed = [0; 8];
+        f.read_exact(&mut seed)?;
+        let seed = u64::from_le_bytes(seed);
+
+        let width = read_u32(&mut f)? as usize;
+        let height = read_u32(&mut f)? as usize;
+        let frame = read_u32(&mut f)? as usize;
+        let next_sample = read_u32(&mut f)? as usize;
+        let filter = read_string(&mut f)?;
+        let filter = FilterKind::from_name(&filter)
+            .ok_or_else(|| invalid(format!("unknown filter '{}'", filter)))?;
+        let filter = Filter::new(filter, read_f32(&mut f)?);
+        let integrator = read_string(&mut f)?;
+        let integrator = IntegratorKind::from_name(&integrator)
+            .ok_or_else(|| invalid(format!("unknown integrator '{}'", integrator)))?;
+        let debug = match read_string(&mut f)?.as_str() {
+            "" => None,
+            view => Some(
+                DebugView::from_name(view)
+                    .ok_or_else(|| invalid(format!("unknown debug view '{}'", view)))?,
+            ),
+        };
+        let mis_heuristic = read_string(&mut f)?;
+        let mis_heuristic = MisHeuristic::from_name(&mis_heuristic)
+            .ok_or_else(|| invalid(format!("unknown mis heuristic '{}'", mis_heuristic)))?;
+        let sampler = read_string(&mut f)?;
+        let sampler = SamplerKind::from_name(&sampler)
+            .ok_or_else(|| invalid(format!("unknown sampler '{}'", sampler)))?;
+        let scramble = read_string(&mut f)?;
+        let scramble = Scramble::from_name(&scramble)
+            .ok_or_else(|| invalid(format!("unknown scramble '{}'", scramble)))?;
+        let max_depth = read_u32(&mut f)?;
+        let rr_depth = read_u32(&mut f)?;
+        let mut photons = [0; 8];
+        f.read_exact(&mut photons)?;
+        let photons = u64::from_le_bytes(photons) as usize;
+        let photon_radius = Some(read_f32(&mut f)?).filter(|&r| r != 0.0);
+        let pass_samples = read_u32(&mut f)? as usize;
+        let aovs = (0..read_u32(&mut f)?)
+            .map(|_| {
+                let name = read_string(&mut f)?;
+                Aov::from_name(&name).ok_or_else(|| invalid(format!("unknown aov '{}'", name)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        // the pixels must be all that is left of the file, checked before allocating
+        // anything from the size it claims
+        let pixel_bytes = 48 + 24 * aovs.len() as u64;
+        let left = f.get_ref().metadata()?.len() - f.stream_position()?;
+        if (width as u64)
+            .checked_mul(height as u64)
+            .and_then(|n| n.checked_mul(pixel_bytes))
+            != Some(left)
+        {
+            return Err(invalid(format!(
+                "{} doesn't hold a {}x{} film",
+                path.display(),
+                width,
+                height
+            )));
+        }
+
+        let mut film = Film::new(width, height, filter, &aovs);
+        for i in 0..width * height {
+            film.sum[i] = Color::new(read_f32(&mut f)?, read_f32(&mut f)?, read_f32(&mut f)?);
+            film.sum_sq[i] = read_f32(&mut f)?;
+            film.samples[i] = read_u32(&mut f)?;
+            film.filtered[i] = Color::new(read_f32(&mut f)?, read_f32(&mut f)?, read_f32(&mut f)?);
+            film.filter_weights[i] = read_f32(&mut f)?;
+            film.splat[i] = Color::new(read_f32(&mut f)?, read_f32(&mut f)?, read_f32(&mut f)?);
+            for buffer in &mut film.aovs {
+                buffer.sum[i] = Color::new(read_f32(&mut f)?, read_f32(&mut f)?, read_f32(&mut f)?);
+                buffer.splat[i] =
+                    Color::new(read_f32(&mut f)?, read_f32(&mut f)?, read_f32(&mut f)?);
+            }
+        }
+
+        Ok(Self {
+            scene,
+            seed,
+            integrator,
+            debug,
+            mis_heuristic,
+            max_depth,
+            rr_depth,
+            photons,
+            photon_radius,
+            sampler,
+            scramble,
+            pass_samples,
+            frame,
+            next_sample,
+            film,
+        })
+    }
+
+    // refuse to resume into a render with a different setup
+    pub fn check(&self, settings: &Settings) -> io::Result<()> {
+        let (scene, width, height) = (settings.scene.as_str(), settings.width, settings.height);
+        if self.scene != scene || self.film.width != width || self.film.height != height {
+            return Err(invalid(format!(
+                "checkpoint is for {} at {}x{}, not {} at {}x{}",
+                self.scene, self.film.width, self.film.height, scene, width, height
+            )));
+        }
+        if self.seed != settings.seed {
+            return Err(invalid(format!(
+                "checkpoint was saved with --seed {}",
+                self.seed
+            )));
+        }
+        if self.integrator != settings.integrator {
+            return Err(invalid(format!(
+                "checkpoint was saved with --integrator {}",
+                self.integrator.name()
+            )));
+        }
+        if self.debug != settings.debug {
+            return Err(invalid(match self.debug {
+                Some(view) => format!("checkpoint was saved with --debug {}", view.name()),
+                None => "checkpoint was saved without --debug".to_string(),
+            }));
+        }
+        if self.mis_heuristic != settings.mis_heuristic {
+            return Err(invalid(format!(
+                "checkpoint was saved with --mis-heuristic {}",
+                self.mis_heuristic.name()
+            )));
+        }
+        if self.max_depth != settings.max_depth || self.rr_depth != settings.rr_depth {
+            return Err(invalid(format!(
+                "checkpoint was saved with --max-depth {} --rr-depth {}",
+                self.max_depth, self.rr_depth
+            )));
+        }
+        if self.photons != settings.photons || self.photon_radius != settings.photon_radius {
+            let radius = match self.photon_radius {
+                Some(r) => format!("--photon-radius {}", r),
+                None => "the default --photon-radius".to_string(),
+            };
+            return Err(invalid(format!(
+                "checkpoint was saved with --photons {} and {}",
+                self.photons, radius
+            )));
+        }
+        if self.sampler != settings.sampler || self.scramble != settings.scramble {
+            return Err(invalid(format!(
+                "checkpoint was saved with --sampler {} --scramble {}",
+                self.sampler.name(),
+                self.scramble.name()
+            )));
+        }
+        // progressive photon mapping also shrinks its radius by the pass index
+        if self.pass_samples != settings.pass_samples() {
+            return Err(invalid(format!(
+                "checkpoint was saved with passes of {} samples",
+                self.pass_samples
+            )));
+        }
+        if self.film.filter != settings.filter() {
+            return Err(invalid(format!(
+                "checkpoint was saved with the {} filter of radius {}",
+                self.film.filter.kind.name(),
+                self.film.filter.radius
+            )));
+        }
+        if !self
+            .film
+            .aovs
+            .iter()
+            .map(|b| b.aov)
+            .eq(settings.film_aovs())
+        {
+            return Err(invalid(
+                "checkpoint was saved with other --aov buffers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u32(w: &mut impl Write, v: u32) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_string(r: &mut impl Read) -> io::Result<String> {
+    let len = read_u32(r)?;
+    if len > MAX_STRING {
+        return Err(invalid("bad string in checkpoint".to_string()));
+    }
+    let mut buf = vec![0; len as usize];
+    r.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|_| invalid("bad string in checkpoint".to_string()))
+}
+
+fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
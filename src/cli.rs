@@ -0,0 +1,455 @@
+use crate::aov::Aov;
+use crate::debug::DebugView;
+use crate::filter::{Filter, FilterKind};
+use crate::integrator::{IntegratorKind, MisHeuristic};
+use crate::output::{self, Format};
+use crate::sampler::{SamplerConfig, SamplerKind, Scramble};
+use crate::scenes;
+use crate::tonemap::{ToneMapper, ToneMapping};
+
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "\
+usage: rei-treicem [options]
+
+options:
+    --scene NAME        scene to render (default: cornell_box)
+    --width N           image width in pixels (default: 500)
+    --height N          image height in pixels (default: same as width)
+    --spp N             samples per pixel (default: 100)
+    --sampler NAME      where samples are placed: independent, stratified, halton
+                        or sobol (default: sobol)
+    --scramble NAME     how halton and sobol samples are randomized per pixel:
+                        none, rotation or owen (default: owen)
+    --seed N            seed of the samples and of scenes placing things at
+                        random, the same seed renders the same image (default: 0)
+    --integrator NAME   light transport algorithm: path, mis, bdpt or ppm
+                        (default: mis)
+    --mis-heuristic H   balance or power (default: power)
+    --max-depth N       maximum path depth (default: 50)
+    --rr-depth N        bounces before russian roulette may end a path (default: 3)
+    --photons N         photons shot per pass by ppm (default: 100000)
+    --photon-radius R   gather radius of the first ppm pass (default: a hundredth
+                        of the visible scene size)
+    --debug VIEW        show the first hit of each camera ray instead of lighting
+                        it: normals, depth, uv, front-face, albedo, object,
+                        material or heat (BVH nodes visited)
+    --aov LIST          also write these comma separated buffers: albedo, normal,
+                        depth, position, emission, direct, indirect or all. .exr
+                        output gets them as layers, other formats as files
+                        named like image.albedo.png
+    --denoise           filter the noise out of the image, guided by the albedo,
+                        normal and depth of the first hits
+    --split-stereo      with a stereo camera, also write each eye's half of the
+                        image on its own, named like image.left.png
+    --filter NAME       pixel reconstruction filter: box, tent, gaussian, mitchell
+                        or lanczos (default: box)
+    --filter-radius R   filter radius in pixels (default: 0.5 for box, 1 for tent,
+                        1.5 for gaussian, 2 for mitchell and lanczos)
+    --progressive N     render in passes of N samples per pixel and write a
+                        snapshot of the output after each pass
+    --snapshot-every S  only write progressive snapshots S seconds apart
+    --adaptive E        keep sampling a pixel only while the relative error of
+                        its mean exceeds E, --spp becomes the maximum
+    --time-limit S      keep rendering passes of a frame until the next one would
+                        take it past S seconds
+    --target-error E    keep rendering passes of a frame until the relative error
+                        of the pixel means, averaged over the image, is below E.
+                        With either of these --spp becomes the maximum, which
+                        defaults to 65536
+    --sample-heatmap PATH
+                        write the number of samples each pixel took
+    --checkpoint PATH   save the render state to PATH after each pass, passes
+                        are 16 samples unless --progressive says otherwise
+    --checkpoint-every S
+                        only save checkpoints S seconds apart
+    --resume            continue from the --checkpoint file if it exists
+    --stats             print what the render took at the end: rays traced by
+                        kind and per second, BVH nodes and primitives tested
+                        per ray, path lengths and samples that came out NaN
+    --stats-json PATH   write the same statistics to PATH as JSON
+    --threads N         worker threads (default: one per core)
+    --tile-size N       edge length of the square render tiles (default: 32)
+    -o, --output PATH   output image, format chosen by extension:
+                        .ppm, .png, .hdr or .exr (default: image.ppm)
+    --bit-depth 8|16    png bits per channel (default: 8)
+    --exposure EV       brighten .ppm and .png output by EV stops (default: 0)
+    --tonemap NAME      squeeze .ppm and .png output into range with clamp,
+                        reinhard, reinhard-extended, aces or agx (default: clamp)
+    --white-point L     luminance reinhard-extended maps to white (default: the
+                        brightest pixel)
+    --list-scenes       print the available scenes and exit
+    --help              print this message and exit";
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub scene: String,
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub sampler: SamplerKind,
+    pub scramble: Scramble,
+    pub seed: u64,
+    pub integrator: IntegratorKind,
+    pub mis_heuristic: MisHeuristic,
+    pub max_depth: u32,
+    pub rr_depth: u32,
+    pub photons: usize,
+    pub photon_radius: Option<f32>,
+    pub debug: Option<DebugView>,
+    pub aovs: Vec<Aov>,
+    pub denoise: bool,
+    pub split_stereo: bool,
+    pub filter: FilterKind,
+    pub filter_radius: Option<f32>,
+    pub pass_samples: Option<usize>,
+    pub snapshot_interval: Option<f32>,
+    pub adaptive_threshold: Option<f32>,
+    pub time_limit: Option<f32>,
+    pub target_error: Option<f32>,
+    pub sample_heatmap: Option<PathBuf>,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Option<f32>,
+    pub resume: bool,
+    pub stats: bool,
+    pub stats_json: Option<PathBuf>,
+    pub threads: Option<usize>,
+    pub tile_size: usize,
+    pub output: PathBuf,
+    pub bit_depth: u8,
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+    pub white_point: Option<f32>,
+}
+
+pub enum Command {
+    Render(Box<Settings>),
+    ListScenes,
+    Help,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            scene: "cornell_box".to_string(),
+            width: 500,
+            height: 500,
+            samples_per_pixel: 100,
+            sampler: SamplerKind::Sobol,
+            scramble: Scramble::Owen,
+            seed: 0,
+            integrator: IntegratorKind::Mis,
+            mis_heuristic: MisHeuristic::Power,
+            max_depth: 50,
+            rr_depth: 3,
+            photons: 100000,
+            photon_radius: None,
+            debug: None,
+            aovs: vec![],
+            denoise: false,
+            split_stereo: false,
+            filter: FilterKind::Box,
+            filter_radius: None,
+            pass_samples: None,
+            snapshot_interval: None,
+            adaptive_threshold: None,
+            time_limit: None,
+            target_error: None,
+            sample_heatmap: None,
+            checkpoint: None,
+            checkpoint_interval: None,
+            resume: false,
+            stats: false,
+            stats_json: None,
+            threads: None,
+            tile_size: 32,
+            output: PathBuf::from("image.ppm"),
+            bit_depth: 8,
+            exposure: 0.0,
+            tone_mapper: ToneMapper::Clamp,
+            white_point: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn frame_output(&self, frame: usize, frames: usize) -> PathBuf {
+        frame_path(&self.output, frame, frames)
+    }
+
+    // AOVs the film accumulates, the denoiser needs its guides even if they aren't written
+    pub fn film_aovs(&self) -> Vec<Aov> {
+        let mut aovs = self.aovs.clone();
+        if self.denoise {
+            for aov in [Aov::Albedo, Aov::Normal, Aov::Depth] {
+                if !aovs.contains(&aov) {
+                    aovs.push(aov);
+                }
+            }
+        }
+        aovs
+    }
+
+    pub fn sampler(&self) -> SamplerConfig {
+        SamplerConfig::new(self.sampler, self.scramble, self.seed)
+    }
+
+    pub fn filter(&self) -> Filter {
+        let radius = self.filter_radius.unwrap_or_else(|| self.filter.default_radius());
+        Filter::new(self.filter, radius)
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        ToneMapping {
+            operator: self.tone_mapper,
+            exposure: self.exposure,
+            white_point: self.white_point,
+        }
+    }
+
+    // whether passes continue until a time or error budget runs out rather than to --spp
+    pub fn has_budget(&self) -> bool {
+        self.time_limit.is_some() || self.target_error.is_some()
+    }
+
+    // samples per pass, progressive, adaptive, budgeted and checkpointed renders default to
+    // 16 and ppm needs a photon map per sample
+    pub fn pass_samples(&self) -> usize {
+        match self.pass_samples {
+            Some(n) => n,
+            None if self.integrator == IntegratorKind::Ppm => 1,
+            None if self.adaptive_threshold.is_some()
+                || self.has_budget()
+                || self.checkpoint.is_some() =>
+            {
+                16.min(self.samples_per_pixel)
+            }
+            None => self.samples_per_pixel,
+        }
+    }
+}
+
+// animated scenes get the frame number appended to the file stem
+pub fn frame_path(path: &Path, frame: usize, frames: usize) -> PathBuf {
+    if frames <= 1 {
+        return path.to_path_buf();
+    }
+
+    output::stem_suffix(path, &format!("{:03}", frame))
+}
+
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut settings = Settings::default();
+    let mut height = None;
+    let mut samples_per_pixel = None;
+    let mut args = args;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" => return Ok(Command::Help),
+            "--list-scenes" => return Ok(Command::ListScenes),
+            "--scene" => {
+                let name = value(&arg, args.next())?;
+                if scenes::by_name(&name).is_none() {
+                    return Err(format!("unknown scene '{}', see --list-scenes", name));
+                }
+                settings.scene = name;
+            }
+            "--width" => settings.width = number(&arg, args.next())?,
+            "--height" => height = Some(number(&arg, args.next())?),
+            "--spp" => samples_per_pixel = Some(number(&arg, args.next())?),
+            "--sampler" => {
+                let name = value(&arg, args.next())?;
+                settings.sampler = SamplerKind::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown sampler '{}', use one of: {}",
+                        name,
+                        SamplerKind::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--seed" => settings.seed = number(&arg, args.next())?,
+            "--scramble" => {
+                let name = value(&arg, args.next())?;
+                settings.scramble = Scramble::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown scramble '{}', use one of: {}",
+                        name,
+                        Scramble::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--integrator" => {
+                let name = value(&arg, args.next())?;
+                settings.integrator = IntegratorKind::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown integrator '{}', use one of: {}",
+                        name,
+                        IntegratorKind::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--mis-heuristic" => {
+                let name = value(&arg, args.next())?;
+                settings.mis_heuristic = MisHeuristic::from_name(&name)
+                    .ok_or_else(|| format!("unknown mis heuristic '{}'", name))?;
+            }
+            "--max-depth" => settings.max_depth = number(&arg, args.next())?,
+            "--rr-depth" => settings.rr_depth = number(&arg, args.next())?,
+            "--photons" => settings.photons = number(&arg, args.next())?,
+            "--photon-radius" => settings.photon_radius = Some(number(&arg, args.next())?),
+            "--debug" => {
+                let name = value(&arg, args.next())?;
+                settings.debug = Some(DebugView::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown debug view '{}', use one of: {}",
+                        name,
+                        DebugView::NAMES.join(", ")
+                    )
+                })?);
+            }
+            "--aov" => {
+                for name in value(&arg, args.next())?.split(',') {
+                    let aovs = match name {
+                        "all" => Aov::ALL.to_vec(),
+                        _ => vec![Aov::from_name(name)
+                            .ok_or_else(|| format!("unknown aov '{}'", name))?],
+                    };
+                    for aov in aovs {
+                        if !settings.aovs.contains(&aov) {
+                            settings.aovs.push(aov);
+                        }
+                    }
+                }
+            }
+            "--denoise" => settings.denoise = true,
+            "--split-stereo" => settings.split_stereo = true,
+            "--filter" => {
+                let name = value(&arg, args.next())?;
+                settings.filter = FilterKind::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown filter '{}', use one of: {}",
+                        name,
+                        FilterKind::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--filter-radius" => settings.filter_radius = Some(number(&arg, args.next())?),
+            "--progressive" => settings.pass_samples = Some(number(&arg, args.next())?),
+            "--snapshot-every" => settings.snapshot_interval = Some(number(&arg, args.next())?),
+            "--adaptive" => settings.adaptive_threshold = Some(number(&arg, args.next())?),
+            "--time-limit" => settings.time_limit = Some(number(&arg, args.next())?),
+            "--target-error" => settings.target_error = Some(number(&arg, args.next())?),
+            "--sample-heatmap" => {
+                settings.sample_heatmap = Some(PathBuf::from(value(&arg, args.next())?))
+            }
+            "--checkpoint" => settings.checkpoint = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--checkpoint-every" => {
+                settings.checkpoint_interval = Some(number(&arg, args.next())?)
+            }
+            "--resume" => settings.resume = true,
+            "--stats" => settings.stats = true,
+            "--stats-json" => {
+                settings.stats_json = Some(PathBuf::from(value(&arg, args.next())?))
+            }
+            "--threads" => settings.threads = Some(number(&arg, args.next())?),
+            "--tile-size" => settings.tile_size = number(&arg, args.next())?,
+            "-o" | "--output" => settings.output = PathBuf::from(value(&arg, args.next())?),
+            "--bit-depth" => settings.bit_depth = number(&arg, args.next())?,
+            "--exposure" => settings.exposure = number(&arg, args.next())?,
+            "--tonemap" => {
+                let name = value(&arg, args.next())?;
+                settings.tone_mapper = ToneMapper::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown tone mapper '{}', use one of: {}",
+                        name,
+                        ToneMapper::NAMES.join(", ")
+                    )
+                })?;
+            }
+            "--white-point" => settings.white_point = Some(number(&arg, args.next())?),
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+
+    settings.height = height.unwrap_or(settings.width);
+    settings.samples_per_pixel = match samples_per_pixel {
+        Some(n) => n,
+        None if settings.has_budget() => 65536,
+        None => settings.samples_per_pixel,
+    };
+
+    if settings.width == 0 || settings.height == 0 {
+        return Err("image dimensions must be greater than zero".to_string());
+    }
+    if settings.samples_per_pixel == 0 {
+        return Err("--spp must be greater than zero".to_string());
+    }
+    if settings.photons == 0 {
+        return Err("--photons must be greater than zero".to_string());
+    }
+    if settings.photon_radius.is_some_and(|r: f32| r.is_nan() || r <= 0.0) {
+        return Err("--photon-radius must be greater than zero".to_string());
+    }
+    if settings.filter_radius.is_some_and(|r: f32| !r.is_finite() || r <= 0.0) {
+        return Err("--filter-radius must be greater than zero".to_string());
+    }
+    if settings.time_limit.is_some_and(|s: f32| s.is_nan() || s <= 0.0) {
+        return Err("--time-limit must be greater than zero".to_string());
+    }
+    if settings.target_error.is_some_and(|e: f32| e.is_nan() || e <= 0.0) {
+        return Err("--target-error must be greater than zero".to_string());
+    }
+    if settings.pass_samples == Some(0) {
+        return Err("--progressive must be greater than zero".to_string());
+    }
+    if settings.snapshot_interval.is_some() && settings.pass_samples.is_none() {
+        return Err("--snapshot-every needs --progressive".to_string());
+    }
+    if (settings.resume || settings.checkpoint_interval.is_some()) && settings.checkpoint.is_none()
+    {
+        return Err("--resume and --checkpoint-every need --checkpoint".to_string());
+    }
+    for path in Some(&settings.output).iter().chain(&settings.sample_heatmap.as_ref()) {
+        if Format::from_path(path).is_none() {
+            return Err(format!(
+                "can't tell the output format of '{}', use .ppm, .png, .hdr or .exr",
+                path.display()
+            ));
+        }
+    }
+    if settings.sample_heatmap.is_some() && settings.adaptive_threshold.is_none() {
+        return Err("--sample-heatmap needs --adaptive".to_string());
+    }
+    if settings.bit_depth != 8 && settings.bit_depth != 16 {
+        return Err("--bit-depth must be 8 or 16".to_string());
+    }
+    if !settings.exposure.is_finite() {
+        return Err("--exposure must be a number".to_string());
+    }
+    if settings.white_point.is_some_and(|w: f32| w.is_nan() || w <= 0.0) {
+        return Err("--white-point must be greater than zero".to_string());
+    }
+    if settings.tile_size == 0 {
+        return Err("--tile-size must be greater than zero".to_string());
+    }
+    if settings.threads == Some(0) {
+        return Err("--threads must be greater than zero".to_string());
+    }
+
+    Ok(Command::Render(Box::new(settings)))
+}
+
+fn value(flag: &str, arg: Option<String>) -> Result<String, String> {
+    arg.ok_or_else(|| format!("missing value for '{}'", flag))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, arg: Option<String>) -> Result<T, String> {
+    let arg = value(flag, arg)?;
+    arg.parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", arg, flag))
+}
@@ -0,0 +1,146 @@
+use crate::film::{heat_color, Splat};
+use crate::hittable::Hittable;
+use crate::integrator::*;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::stats::{self, RayKind};
+use crate::vec3::*;
+
+// BVH nodes a camera ray can visit before the heat view turns white
+const HEAT_MAX: f32 = 256.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugView {
+    Normals,
+    Depth,
+    Uv,
+    FrontFace,
+    Albedo,
+    Object,
+    Material,
+    Heat,
+}
+
+impl DebugView {
+    pub const NAMES: &'static [&'static str] = &[
+        "normals",
+        "depth",
+        "uv",
+        "front-face",
+        "albedo",
+        "object",
+        "material",
+        "heat",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugView::Normals => "normals",
+            DebugView::Depth => "depth",
+            DebugView::Uv => "uv",
+            DebugView::FrontFace => "front-face",
+            DebugView::Albedo => "albedo",
+            DebugView::Object => "object",
+            DebugView::Material => "material",
+            DebugView::Heat => "heat",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "normals" => Some(DebugView::Normals),
+            "depth" => Some(DebugView::Depth),
+            "uv" => Some(DebugView::Uv),
+            "front-face" => Some(DebugView::FrontFace),
+            "albedo" => Some(DebugView::Albedo),
+            "object" => Some(DebugView::Object),
+            "material" => Some(DebugView::Material),
+            "heat" => Some(DebugView::Heat),
+            _ => None,
+        }
+    }
+}
+
+// Shows what the first hit of each camera ray looks like to the renderer instead of
+// lighting it, for tracking down importer and transform bugs. Misses are black.
+pub struct DebugIntegrator {
+    pub view: DebugView,
+}
+
+impl DebugIntegrator {
+    pub fn new(view: DebugView) -> Self {
+        Self { view }
+    }
+
+    fn color(&self, ray: Ray, scene: &SceneView) -> Color {
+        if self.view == DebugView::Object {
+            return match closest_object(&ray, scene) {
+                Some(index) => id_color(index as u64),
+                None => Color::new_empty(),
+            };
+        }
+
+        let visits = stats::local_node_visits();
+        let hit = stats::trace(scene.world, &ray, f32::INFINITY, RayKind::Camera);
+        if self.view == DebugView::Heat {
+            let visits = stats::local_node_visits() - visits;
+            return heat_color(visits as f32 / HEAT_MAX);
+        }
+
+        let hit = match hit {
+            Some(hit) => hit,
+            None => return Color::new_empty(),
+        };
+
+        match self.view {
+            DebugView::Normals => 0.5 * (hit.normal + Color::new(1.0, 1.0, 1.0)),
+            // distance in scene units, write .exr or .hdr to keep it
+            DebugView::Depth => {
+                let depth = hit.t * ray.dir.length();
+                Color::new(depth, depth, depth)
+            }
+            DebugView::Uv => Color::new(hit.u, hit.v, 0.0),
+            DebugView::FrontFace if hit.front_face => Color::new(0.0, 1.0, 0.0),
+            DebugView::FrontFace => Color::new(1.0, 0.0, 0.0),
+            DebugView::Albedo => hit.material.albedo(&hit),
+            DebugView::Material => id_color(hit.material.id()),
+            DebugView::Object | DebugView::Heat => unreachable!("handled above"),
+        }
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn li(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        _sampler: &mut dyn Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Radiance {
+        // everything counts as seen directly
+        Radiance {
+            emitted: self.color(ray, scene),
+            ..Radiance::default()
+        }
+    }
+}
+
+// number of the object `ray` hits first, counting the ones inside lists and BVHs
+fn closest_object(ray: &Ray, scene: &SceneView) -> Option<usize> {
+    scene
+        .world
+        .hit_object(ray, 0.001, f32::INFINITY)
+        .map(|(_, index)| index)
+}
+
+// a stable, bright colour for an id, neighbouring ids get unrelated colours
+fn id_color(id: u64) -> Color {
+    // splitmix64 finaliser
+    let mut x = id.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+
+    let channel = |shift: u32| 0.2 + 0.8 * ((x >> shift) & 0xff) as f32 / 255.0;
+    Color::new(channel(0), channel(8), channel(16))
+}
@@ -0,0 +1,141 @@
+// Edge-avoiding à-trous wavelet filter, as in "Spatiotemporal Variance-Guided Filtering"
+// (Schied et al. 2017) without the temporal part. Lighting is divided by the albedo so
+// texture detail survives, then blurred with a 5x5 kernel spread wider each iteration.
+// Taps across geometric edges are cut off by the normals and depth, and taps whose
+// brightness differs by more than the pixel's noise level by the luminance.
+
+use crate::vec3::*;
+
+use rayon::prelude::*;
+
+// B3 spline taps
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const ITERATIONS: usize = 5;
+
+// edge stopping strengths, lower is stricter for luminance and depth, higher for normals
+const SIGMA_LUMINANCE: f32 = 4.0;
+const SIGMA_NORMAL: f32 = 128.0;
+const SIGMA_DEPTH: f32 = 1.0;
+
+// First hit buffers of the image being denoised, see the aov module.
+pub struct Guides {
+    pub albedo: Vec<Color>,
+    pub normal: Vec<Color>,
+    pub depth: Vec<Color>,
+}
+
+// `variance` is the variance of each pixel's mean luminance, it sets how much brightness
+// difference counts as noise.
+pub fn denoise(
+    width: usize,
+    height: usize,
+    color: &[Color],
+    variance: &[f32],
+    guides: &Guides,
+) -> Vec<Color> {
+    // black albedo has no lighting to recover, those pixels are filtered as they are
+    let albedo = guides
+        .albedo
+        .iter()
+        .map(|a| {
+            if a.luminance() > 0.01 {
+                Color::new(a.x.max(0.01), a.y.max(0.01), a.z.max(0.01))
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            }
+        })
+        .collect::<Vec<_>>();
+    let normal = guides
+        .normal
+        .iter()
+        .map(|n| if n.near_zero() { *n } else { n.unit_vector() })
+        .collect::<Vec<_>>();
+    let depth = guides.depth.iter().map(|d| d.x).collect::<Vec<_>>();
+    let depth_gradient = gradient(width, height, &depth);
+
+    let mut lighting = color
+        .iter()
+        .zip(&albedo)
+        .map(|(&c, &a)| Color::new(c.x / a.x, c.y / a.y, c.z / a.z))
+        .collect::<Vec<_>>();
+    // too few samples to tell means anything goes
+    let mut variance = variance
+        .iter()
+        .zip(&albedo)
+        .map(|(&v, a)| v.min(1e6) / a.luminance().powi(2))
+        .collect::<Vec<_>>();
+
+    for iteration in 0..ITERATIONS {
+        let step = 1 << iteration;
+        let filtered = (0..width * height)
+            .into_par_iter()
+            .map(|p| {
+                let (x, y) = ((p % width) as isize, (p / width) as isize);
+                let luminance = lighting[p].luminance();
+                let sigma_luminance = SIGMA_LUMINANCE * variance[p].sqrt() + 1e-4;
+
+                let mut sum = Color::new_empty();
+                let mut weights = 0.0;
+                let mut sum_variance = 0.0;
+
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let (ox, oy) = (dx as isize - 2, dy as isize - 2);
+                        let (qx, qy) = (x + ox * step, y + oy * step);
+                        if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                            continue;
+                        }
+                        let q = qy as usize * width + qx as usize;
+
+                        let w_normal = normal_weight(normal[p], normal[q]);
+                        let distance = step as f32 * ((ox * ox + oy * oy) as f32).sqrt();
+                        let w_depth = (-(depth[p] - depth[q]).abs()
+                            / (SIGMA_DEPTH * depth_gradient[p] * distance + 1e-4))
+                            .exp();
+                        let w_luminance =
+                            (-(luminance - lighting[q].luminance()).abs() / sigma_luminance).exp();
+
+                        let w = kx * ky * w_normal * w_depth * w_luminance;
+                        sum += w * lighting[q];
+                        weights += w;
+                        sum_variance += w * w * variance[q];
+                    }
+                }
+
+                if weights > 0.0 {
+                    (sum / weights, sum_variance / (weights * weights))
+                } else {
+                    (lighting[p], variance[p])
+                }
+            })
+            .collect::<Vec<_>>();
+
+        lighting = filtered.iter().map(|f| f.0).collect();
+        variance = filtered.iter().map(|f| f.1).collect();
+    }
+
+    lighting.iter().zip(&albedo).map(|(&l, &a)| l * a).collect()
+}
+
+// pixels where the camera ray missed have no normal and only mix with each other
+fn normal_weight(a: Vec3, b: Vec3) -> f32 {
+    match (a.near_zero(), b.near_zero()) {
+        (true, true) => 1.0,
+        (false, false) => a.dot(b).max(0.0).powf(SIGMA_NORMAL),
+        _ => 0.0,
+    }
+}
+
+// how much a buffer changes from one pixel to the next, the larger of both axes
+fn gradient(width: usize, height: usize, values: &[f32]) -> Vec<f32> {
+    (0..width * height)
+        .map(|p| {
+            let (x, y) = (p % width, p / width);
+            let dx = values[y * width + (x + 1).min(width - 1)]
+                - values[y * width + x.saturating_sub(1)];
+            let dy = values[(y + 1).min(height - 1) * width + x]
+                - values[y.saturating_sub(1) * width + x];
+            dx.abs().max(dy.abs()) / 2.0
+        })
+        .collect()
+}
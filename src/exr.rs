@@ -0,0 +1,114 @@
+// Minimal OpenEXR writer: single part, scanline, uncompressed, 32-bit float channels.
+// https://www.openexr.com/documentation/openexrfilelayout.pdf
+
+use std::io::{self, Write};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: u32 = 2;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+pub struct Channel {
+    pub name: String,
+    // row-major, top scanline first
+    pub data: Vec<f32>,
+}
+
+impl Channel {
+    pub fn new(name: &str, data: Vec<f32>) -> Self {
+        Self {
+            name: name.to_string(),
+            data,
+        }
+    }
+}
+
+// `attributes` are extra name and value pairs stored in the header as strings
+pub fn write(
+    w: &mut impl Write,
+    width: usize,
+    height: usize,
+    channels: &mut [Channel],
+    attributes: &[(&str, String)],
+) -> io::Result<()> {
+    // readers expect the channel list, and the data within each scanline, sorted by name
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for channel in channels.iter() {
+        if channel.data.len() != width * height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("exr channel '{}' has the wrong size", channel.name),
+            ));
+        }
+    }
+
+    let mut header = vec![];
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+
+    let mut chlist = vec![];
+    for channel in channels.iter() {
+        chlist.extend_from_slice(channel.name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear + 3 reserved bytes
+        chlist.extend_from_slice(&[0, 0, 0, 0]);
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+    }
+    chlist.push(0);
+
+    let mut window = vec![];
+    for v in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+
+    attribute(&mut header, "channels", "chlist", &chlist);
+    attribute(&mut header, "compression", "compression", &[0]);
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    for (name, value) in attributes {
+        attribute(&mut header, name, "string", value.as_bytes());
+    }
+    header.push(0);
+
+    // one scanline per block: y, byte count, then every channel's samples for that line
+    let block_size = 8 + width * 4 * channels.len();
+    let table_size = 8 * height;
+
+    let mut offset = (header.len() + table_size) as u64;
+    for _ in 0..height {
+        header.extend_from_slice(&offset.to_le_bytes());
+        offset += block_size as u64;
+    }
+    w.write_all(&header)?;
+
+    let mut block = Vec::with_capacity(block_size);
+    for y in 0..height {
+        block.clear();
+        block.extend_from_slice(&(y as i32).to_le_bytes());
+        block.extend_from_slice(&((block_size - 8) as i32).to_le_bytes());
+
+        for channel in channels.iter() {
+            for v in &channel.data[y * width..(y + 1) * width] {
+                block.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        w.write_all(&block)?;
+    }
+
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
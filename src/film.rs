@@ -0,0 +1,313 @@
+use crate::aov::Aov;
+use crate::filter::Filter;
+use crate::render::TileBuffer;
+use crate::vec3::*;
+
+use std::ops::AddAssign;
+
+// Light a sample deposits on whichever pixel sees image plane coordinates (s, t), used by
+// integrators that connect light subpaths straight to the camera.
+#[derive(Debug, Clone, Copy)]
+pub struct Splat {
+    pub s: f32,
+    pub t: f32,
+    pub color: Color,
+    // bounces the light took, for the light AOVs
+    pub bounces: u32,
+}
+
+// What one pass produced for a single pixel. Pixels skipped by the pass have `count` 0.
+// The sums only see the pixel's own samples, what the filter spreads them over goes in
+// the tile's buffer of FilteredSums.
+#[derive(Debug, Clone, Default)]
+pub struct PixelSamples {
+    pub sum: Color,
+    // sum of the squared luminance of every sample, for the variance estimate
+    pub sum_sq: f32,
+    pub count: u32,
+    pub splats: Vec<Splat>,
+    // sums for each of the film's AOVs, in the same order
+    pub aovs: Vec<Color>,
+}
+
+impl PixelSamples {
+    pub fn new(film: &Film) -> Self {
+        Self {
+            aovs: vec![Color::new_empty(); film.aovs.len()],
+            ..Self::default()
+        }
+    }
+
+    // `offset` is where in pixel (x, y) the sample was taken, from its top left corner,
+    // the filter adds it to the pixels within its extent in `filtered`
+    pub fn add(
+        &mut self,
+        c: Color,
+        (x, y): (usize, usize),
+        offset: (f32, f32),
+        filter: &Filter,
+        filtered: &mut TileBuffer<FilteredSum>,
+    ) {
+        let luminance = c.luminance();
+        self.sum += c;
+        self.sum_sq += luminance * luminance;
+        self.count += 1;
+
+        let extent = filter.extent() as isize;
+        for oy in -extent..=extent {
+            for ox in -extent..=extent {
+                let dx = ox as f32 + 0.5 - offset.0;
+                let dy = oy as f32 + 0.5 - offset.1;
+                let weight = filter.weight(dx, dy);
+                let sum = FilteredSum {
+                    color: weight * c,
+                    weight,
+                };
+                filtered.add(x as isize + ox, y as isize + oy, sum);
+            }
+        }
+    }
+}
+
+// Filter weighted radiance landing on a pixel, with the weights to normalise it by.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilteredSum {
+    pub color: Color,
+    pub weight: f32,
+}
+
+impl AddAssign for FilteredSum {
+    fn add_assign(&mut self, other: Self) {
+        self.color += other.color;
+        self.weight += other.weight;
+    }
+}
+
+// Float accumulation buffer for one frame. Stores the radiance sum and the number of
+// samples of every pixel, top row first, so passes can be added as they finish. The image
+// comes from the sums weighted by the reconstruction filter, the plain ones drive the
+// error estimates.
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub filter: Filter,
+    pub sum: Vec<Color>,
+    pub sum_sq: Vec<f32>,
+    pub samples: Vec<u32>,
+    pub filtered: Vec<Color>,
+    pub filter_weights: Vec<f32>,
+    pub splat: Vec<Color>,
+    pub aovs: Vec<AovBuffer>,
+    // what the camera's exposure multiplies the radiance by on the way out, it isn't
+    // saved with checkpoints since the scene sets it again
+    pub exposure: f32,
+}
+
+// An AOV accumulated with the same samples as the image.
+pub struct AovBuffer {
+    pub aov: Aov,
+    pub sum: Vec<Color>,
+    pub splat: Vec<Color>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Filter, aovs: &[Aov]) -> Self {
+        let aovs = aovs
+            .iter()
+            .map(|&aov| AovBuffer {
+                aov,
+                sum: vec![Color::new_empty(); width * height],
+                splat: vec![Color::new_empty(); width * height],
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            filter,
+            sum: vec![Color::new_empty(); width * height],
+            sum_sq: vec![0.0; width * height],
+            samples: vec![0; width * height],
+            filtered: vec![Color::new_empty(); width * height],
+            filter_weights: vec![0.0; width * height],
+            splat: vec![Color::new_empty(); width * height],
+            aovs,
+            exposure: 1.0,
+        }
+    }
+
+    // `filtered` is what the filter spread the pass's samples over, for every pixel
+    pub fn add_pass(&mut self, pass: &[PixelSamples], filtered: &[FilteredSum]) {
+        for (i, p) in pass.iter().enumerate() {
+            self.sum[i] += p.sum;
+            self.sum_sq[i] += p.sum_sq;
+            self.samples[i] += p.count;
+            for (buffer, sum) in self.aovs.iter_mut().zip(&p.aovs) {
+                buffer.sum[i] += *sum;
+            }
+
+            self.filtered[i] += filtered[i].color;
+            self.filter_weights[i] += filtered[i].weight;
+
+            for splat in &p.splats {
+                if let Some(j) = self.splat_pixel(splat.s, splat.t) {
+                    self.splat[j] += splat.color;
+                    for buffer in &mut self.aovs {
+                        if buffer.aov.takes_bounces(splat.bounces) {
+                            buffer.splat[j] += splat.color;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // filtered radiance of every pixel
+    pub fn image(&self) -> Vec<Color> {
+        let splat_scale = self.splat_scale();
+
+        (0..self.width * self.height)
+            .map(|i| {
+                let splat = self.splat[i] * splat_scale;
+                // negative lobes can cancel out where there are hardly any samples
+                let c = if self.filter_weights[i].abs() < 1e-6 {
+                    splat
+                } else {
+                    self.filtered[i] / self.filter_weights[i] + splat
+                };
+                self.exposure * c
+            })
+            .collect()
+    }
+
+    // an AOV averaged over each pixel's own samples, None if the film doesn't have it
+    pub fn aov_image(&self, aov: Aov) -> Option<Vec<Color>> {
+        let buffer = self.aovs.iter().find(|buffer| buffer.aov == aov)?;
+        let mut image = self.average(&buffer.sum, &buffer.splat);
+        if aov.is_light() {
+            for c in &mut image {
+                *c = self.exposure * *c;
+            }
+        }
+        Some(image)
+    }
+
+    fn average(&self, sum: &[Color], splat: &[Color]) -> Vec<Color> {
+        let splat_scale = self.splat_scale();
+
+        sum.iter()
+            .zip(&self.samples)
+            .zip(splat)
+            .map(|((c, &n), &splat)| {
+                let splat = splat * splat_scale;
+                if n == 0 {
+                    splat
+                } else {
+                    *c / n as f32 + splat
+                }
+            })
+            .collect()
+    }
+
+    // the pixel whose camera rays go through (s, t), inverting the mapping in render()
+    fn splat_pixel(&self, s: f32, t: f32) -> Option<usize> {
+        let x = (s * self.width as f32).floor();
+        let y = (t * self.height as f32).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+
+        Some((self.height - 1 - y as usize) * self.width + x as usize)
+    }
+
+    // Every sample of every pixel can splat anywhere, so splats are averaged over all
+    // samples taken and scaled up by the share of the image plane one pixel covers.
+    fn splat_scale(&self) -> f32 {
+        let total = self.samples.iter().map(|&n| n as f64).sum::<f64>();
+        if total == 0.0 {
+            return 0.0;
+        }
+
+        let pixels = self.width * self.height;
+        (pixels as f64 / total) as f32
+    }
+
+    // Standard error of the mean luminance relative to the mean itself. Dark pixels are
+    // measured against a floor of 0.01 so they don't keep sampling noise forever.
+    pub fn relative_error(&self, i: usize) -> f32 {
+        let n = self.samples[i] as f32;
+        if n < 2.0 {
+            return f32::INFINITY;
+        }
+
+        let mean = self.sum[i].luminance() / n;
+        self.mean_variance(i).sqrt() / mean.max(0.01)
+    }
+
+    // relative_error() averaged over the image, how far the render is from converging
+    pub fn mean_relative_error(&self) -> f32 {
+        let pixels = self.width * self.height;
+        let total = (0..pixels)
+            .map(|i| self.relative_error(i) as f64)
+            .sum::<f64>();
+        (total / pixels as f64) as f32
+    }
+
+    // samples taken per pixel, on average
+    pub fn mean_samples(&self) -> f32 {
+        let total = self.samples.iter().map(|&n| n as f64).sum::<f64>();
+        (total / (self.width * self.height) as f64) as f32
+    }
+
+    // Variance of the mean luminance of a pixel, estimated from its samples. Infinite
+    // with fewer than two.
+    pub fn mean_variance(&self, i: usize) -> f32 {
+        let n = self.samples[i] as f32;
+        if n < 2.0 {
+            return f32::INFINITY;
+        }
+
+        let mean = self.sum[i].luminance() / n;
+        let variance = ((self.sum_sq[i] / n - mean * mean) * n / (n - 1.0)).max(0.0);
+        variance / n
+    }
+
+    // Pixels that still need samples. A single pixel's estimate is itself noisy, so the
+    // worst error in its 3x3 neighbourhood decides.
+    pub fn unconverged(&self, threshold: f32) -> Vec<bool> {
+        let error = (0..self.width * self.height)
+            .map(|i| self.relative_error(i))
+            .collect::<Vec<_>>();
+
+        let mut active = vec![false; self.width * self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut ys = y.saturating_sub(1)..(y + 2).min(self.height);
+                active[y * self.width + x] = ys.any(|ny| {
+                    let mut xs = x.saturating_sub(1)..(x + 2).min(self.width);
+                    xs.any(|nx| error[ny * self.width + nx] > threshold)
+                });
+            }
+        }
+
+        active
+    }
+
+    // Samples taken per pixel as a black-red-yellow-white ramp, brightest at `max_samples`.
+    pub fn sample_heatmap(&self, max_samples: usize) -> Vec<Color> {
+        self.samples
+            .iter()
+            .map(|&n| heat_color(n as f32 / max_samples as f32))
+            .collect()
+    }
+}
+
+// black-red-yellow-white ramp over [0, 1]
+pub fn heat_color(t: f32) -> Color {
+    let t = 3.0 * t;
+    Color::new(
+        Vec3::clamp(t, 0.0, 1.0),
+        Vec3::clamp(t - 1.0, 0.0, 1.0),
+        Vec3::clamp(t - 2.0, 0.0, 1.0),
+    )
+}
@@ -1,11 +1,7 @@
 use gltf;
-use gltf::animation::util::{ReadOutputs, Reader};
-use gltf::buffer::Buffer;
 
 use crate::matrix4::Matrix4;
 use crate::vec3::*;
-use crate::material::*;
-use std::sync::Arc;
 
 pub struct GLTF {
     pub nodes: Vec<Node>,
@@ -46,7 +42,7 @@ pub struct GLTFMaterial {
 
 impl GLTF {
     pub fn new(fname: String) -> Result<Self, gltf::Error> {
-        let (document, buffers, images) = gltf::import(fname)?;
+        let (document, buffers, _images) = gltf::import(fname)?;
 
         let (nodes, meshes) = process_nodes(&document, &buffers);
         let materials = process_materials(&document);
@@ -115,14 +111,22 @@ fn process_nodes<'a>(
     process_node_parents(&mut nodes);
     process_global_transforms(&mut nodes);
 
-    for node in nodes {
-        meshes.extend(process_meshes(document.scenes().into_iter().next().nodes()[node.index], buffers).unwrap());
+    for node in &nodes {
+        if let Some(gltf_node) = document.nodes().nth(node.index) {
+            if let Some(node_meshes) = process_meshes(&gltf_node, node.global_transform, buffers) {
+                meshes.extend(node_meshes);
+            }
+        }
     }
 
     (nodes, meshes)
 }
 
-fn process_meshes(node: &gltf::Node, buffers: &[gltf::buffer::Data]) -> Option<Vec<Mesh>> {
+fn process_meshes(
+    node: &gltf::Node,
+    transform: Matrix4,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Vec<Mesh>> {
     node.mesh().map(|mesh| {
         mesh.primitives()
             .map(|primitive| {
@@ -174,7 +178,7 @@ fn process_meshes(node: &gltf::Node, buffers: &[gltf::buffer::Data]) -> Option<V
                     normals,
                     uvs,
                     mat_index,
-                    transform: node.global_transform,
+                    transform,
                 }
             })
             .collect()
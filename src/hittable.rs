@@ -1,14 +1,14 @@
 use crate::aabb::AABB;
 use crate::material::{Isotropic, Material};
 use crate::ray::Ray;
+use crate::sampler::{self, Sampler};
 use crate::texture::Texture;
 use crate::vec3::{Point3, Vec3};
 
 use std::f32;
 use std::sync::Arc;
 
-use rand::prelude::*;
-
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
@@ -31,14 +31,28 @@ impl HitRecord<'_> {
 }
 
 pub trait Hittable: Sync + Send {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
     fn bounding_box(&self, time0: f32, time1: f32) -> Option<AABB>;
     fn pdf_value(&self, _orig: Point3, _v: Vec3) -> f32 {
         0.0
     }
-    fn random(&self, _orig: Vec3) -> Vec3 {
+    fn random(&self, _orig: Vec3, _sampler: &mut dyn Sampler) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+    // A point picked uniformly on the surface, with its outward normal, and the area density
+    // it was picked with. Light subpaths start here, so lights need it.
+    fn sample_surface(&self, _sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        None
+    }
+    // How many objects the object debug view tells apart in here. Lists and BVHs count
+    // what they hold, anything else is a single object.
+    fn object_count(&self) -> usize {
+        1
+    }
+    // hit(), along with which of those objects it was, numbered depth first
+    fn hit_object(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord<'_>, usize)> {
+        self.hit(r, t_min, t_max).map(|hit| (hit, 0))
+    }
 }
 
 #[derive(Clone)]
@@ -46,6 +60,12 @@ pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable>>,
 }
 
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HittableList {
     pub fn new() -> Self {
         Self { objects: vec![] }
@@ -59,6 +79,10 @@ impl HittableList {
         self.objects.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
     pub fn first(&self) -> Option<&Arc<dyn Hittable>> {
         self.objects.first()
     }
@@ -70,10 +94,17 @@ impl HittableList {
     pub fn push_arc(&mut self, object: Arc<dyn Hittable>) {
         self.objects.push(object);
     }
+
+    // the object a uniform sample `u` in [0, 1) picks, each with the same chance
+    fn choose(&self, u: f32) -> Option<&Arc<dyn Hittable>> {
+        let index = (u * self.objects.len() as f32) as usize;
+        self.objects
+            .get(index.min(self.objects.len().saturating_sub(1)))
+    }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut hit: Option<HitRecord> = None;
         let mut closest_so_far = t_max;
 
@@ -87,6 +118,29 @@ impl Hittable for HittableList {
         hit
     }
 
+    fn object_count(&self) -> usize {
+        self.objects
+            .iter()
+            .map(|object| object.object_count())
+            .sum()
+    }
+
+    fn hit_object(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord<'_>, usize)> {
+        let mut hit = None;
+        let mut closest_so_far = t_max;
+        let mut first = 0;
+
+        for object in &self.objects {
+            if let Some((candidate_hit, index)) = object.hit_object(r, t_min, closest_so_far) {
+                closest_so_far = candidate_hit.t;
+                hit = Some((candidate_hit, first + index));
+            }
+            first += object.object_count();
+        }
+
+        hit
+    }
+
     fn bounding_box(&self, time0: f32, time1: f32) -> Option<AABB> {
         if self.objects.is_empty() {
             return None;
@@ -113,6 +167,10 @@ impl Hittable for HittableList {
     }
 
     fn pdf_value(&self, orig: Point3, v: Vec3) -> f32 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
         self.objects
             .iter()
             .map(|h| h.pdf_value(orig, v))
@@ -120,11 +178,17 @@ impl Hittable for HittableList {
             / self.objects.len() as f32
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
-        self.objects
-            .choose(&mut rand::thread_rng())
-            .unwrap()
-            .random(orig)
+    fn random(&self, orig: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        match self.choose(sampler.get_1d()) {
+            Some(object) => object.random(orig, sampler),
+            None => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let object = self.choose(sampler.get_1d())?;
+        let (hr, pdf) = object.sample_surface(sampler)?;
+        Some((hr, pdf / self.objects.len() as f32))
     }
 }
 
@@ -145,7 +209,7 @@ impl ConstantMedium {
 }
 
 impl Hittable for ConstantMedium {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         if let Some(mut rec1) = self.boundary.hit(r, f32::NEG_INFINITY, f32::INFINITY) {
             if let Some(mut rec2) = self.boundary.hit(r, rec1.t + 0.0001, f32::INFINITY) {
                 if rec1.t < t_min {
@@ -165,7 +229,16 @@ impl Hittable for ConstantMedium {
 
                 let ray_length = r.dir.length();
                 let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * rand::random::<f32>().ln();
+                // hit() gets no sampler, the free path comes from a hash of the ray, which
+                // is as well spread as the samples that built the ray
+                let bits = sampler::hash(&[
+                    r.orig.x.to_bits() as u64 | (r.orig.y.to_bits() as u64) << 32,
+                    r.orig.z.to_bits() as u64 | (r.dir.x.to_bits() as u64) << 32,
+                    r.dir.y.to_bits() as u64 | (r.dir.z.to_bits() as u64) << 32,
+                    r.time.to_bits() as u64,
+                ]);
+                let u = 1.0 - (bits >> 40) as f32 / (1u32 << 24) as f32;
+                let hit_distance = self.neg_inv_density * u.ln();
 
                 if hit_distance > distance_inside_boundary {
                     return None;
@@ -207,7 +280,7 @@ impl FlipFace {
 }
 
 impl Hittable for FlipFace {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         if let Some(mut rec) = self.hit.hit(r, t_min, t_max) {
             rec.front_face = !rec.front_face;
             return Some(rec);
@@ -215,7 +288,23 @@ impl Hittable for FlipFace {
         None
     }
 
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let (mut rec, pdf) = self.hit.sample_surface(sampler)?;
+        rec.front_face = !rec.front_face;
+        Some((rec, pdf))
+    }
+
     fn bounding_box(&self, time0: f32, time1: f32) -> Option<AABB> {
         self.hit.bounding_box(time0, time1)
     }
+
+    fn object_count(&self) -> usize {
+        self.hit.object_count()
+    }
+
+    fn hit_object(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord<'_>, usize)> {
+        let (mut rec, i) = self.hit.hit_object(r, t_min, t_max)?;
+        rec.front_face = !rec.front_face;
+        Some((rec, i))
+    }
 }
@@ -0,0 +1,425 @@
+use crate::camera::Camera;
+use crate::film::Splat;
+use crate::hittable::*;
+use crate::material::*;
+use crate::onb::ONB;
+use crate::pdf::*;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::stats::{self, RayKind};
+use crate::vec3::*;
+
+use std::f32::consts::PI;
+
+// What an integrator needs to know about the frame being rendered.
+pub struct SceneView<'a> {
+    pub world: &'a HittableList,
+    pub lights: &'a HittableList,
+    pub background: Color,
+    pub camera: &'a dyn Camera,
+}
+
+impl<'a> SceneView<'a> {
+    // closest hit along `ray`, counted in the render statistics as a ray of `kind`
+    pub fn hit(&self, ray: &Ray, kind: RayKind) -> Option<HitRecord<'a>> {
+        stats::trace(self.world, ray, f32::INFINITY, kind)
+    }
+
+    // solid angle density of sampling `dir` from `orig` with the lights list
+    pub fn light_pdf(&self, orig: Point3, dir: Vec3) -> f32 {
+        if self.lights.objects.is_empty() {
+            0.0
+        } else {
+            self.lights.pdf_value(orig, dir)
+        }
+    }
+}
+
+pub trait Integrator: Sync + Send {
+    // Radiance arriving along `ray`, with every random choice taken from `sampler`. Light
+    // that the sample finds for other pixels goes into `splats`.
+    fn li(
+        &self,
+        ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        splats: &mut Vec<Splat>,
+    ) -> Radiance;
+
+    // called before each pass of samples, with the number of passes rendered before it
+    fn begin_pass(&mut self, _scene: &SceneView, _pass: usize) {}
+}
+
+// Radiance of one camera sample split by how many bounces the light took to reach the
+// camera: none when the camera ray sees an emitter or the background, one when it arrives
+// at the first hit straight from a light, more for the rest.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Radiance {
+    pub emitted: Color,
+    pub direct: Color,
+    pub indirect: Color,
+}
+
+impl Radiance {
+    pub fn add(&mut self, bounces: u32, c: Color) {
+        match bounces {
+            0 => self.emitted += c,
+            1 => self.direct += c,
+            _ => self.indirect += c,
+        }
+    }
+
+    pub fn total(&self) -> Color {
+        self.emitted + self.direct + self.indirect
+    }
+
+    pub fn scaled(&self, weight: f32) -> Self {
+        Self {
+            emitted: weight * self.emitted,
+            direct: weight * self.direct,
+            indirect: weight * self.indirect,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntegratorKind {
+    Path,
+    Mis,
+    Bdpt,
+    Ppm,
+}
+
+impl IntegratorKind {
+    pub const NAMES: &'static [&'static str] = &["path", "mis", "bdpt", "ppm"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegratorKind::Path => "path",
+            IntegratorKind::Mis => "mis",
+            IntegratorKind::Bdpt => "bdpt",
+            IntegratorKind::Ppm => "ppm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "path" => Some(IntegratorKind::Path),
+            "mis" => Some(IntegratorKind::Mis),
+            "bdpt" => Some(IntegratorKind::Bdpt),
+            "ppm" => Some(IntegratorKind::Ppm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MisHeuristic {
+    Balance,
+    Power,
+}
+
+impl MisHeuristic {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MisHeuristic::Balance => "balance",
+            MisHeuristic::Power => "power",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "balance" => Some(MisHeuristic::Balance),
+            "power" => Some(MisHeuristic::Power),
+            _ => None,
+        }
+    }
+
+    // weight of a sample drawn with density `pdf` when `other_pdf` could have drawn it too
+    pub fn weight(&self, pdf: f32, other_pdf: f32) -> f32 {
+        let (a, b) = match self {
+            MisHeuristic::Balance => (pdf, other_pdf),
+            MisHeuristic::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+
+        if a + b > 0.0 {
+            a / (a + b)
+        } else {
+            0.0
+        }
+    }
+
+    // the heuristic applied to the ratio of two densities
+    pub fn ratio(&self, ratio: f32) -> f32 {
+        match self {
+            MisHeuristic::Balance => ratio,
+            MisHeuristic::Power => ratio * ratio,
+        }
+    }
+}
+
+// Unidirectional path tracer. Paths bounce until they escape, hit something that doesn't
+// scatter, reach `max_depth` or lose the russian roulette played after `rr_depth` bounces.
+pub struct PathIntegrator {
+    pub max_depth: u32,
+    pub rr_depth: u32,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: u32, rr_depth: u32) -> Self {
+        Self {
+            max_depth,
+            rr_depth,
+        }
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li(
+        &self,
+        mut ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Radiance {
+        let mut radiance = Radiance::default();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+
+        for depth in 0..self.max_depth {
+            let hit = match scene.hit(&ray, RayKind::path(depth)) {
+                Some(hit) => hit,
+                None => {
+                    radiance.add(depth, throughput * scene.background);
+                    break;
+                }
+            };
+
+            radiance.add(depth, throughput * hit.material.emitted(&ray, &hit));
+
+            match hit.material.scatter(&ray, &hit, sampler) {
+                None => break,
+                Some(ReflectionRecord::Specular {
+                    specular_ray,
+                    attenuation,
+                }) => {
+                    throughput *= attenuation;
+                    ray = specular_ray;
+                }
+                Some(ReflectionRecord::Scatter {
+                    pdf: reflection_pdf,
+                    attenuation,
+                }) => {
+                    let light_pdf = HittablePDF::new(hit.p, scene.lights);
+                    let mixture_pdf = MixturePDF::new(&light_pdf, &*reflection_pdf);
+
+                    // with no lights to aim at, sample the material alone
+                    let scattering: &dyn PDF = if scene.lights.objects.is_empty() {
+                        &*reflection_pdf
+                    } else {
+                        &mixture_pdf
+                    };
+
+                    let scattered = Ray::new(hit.p, scattering.generate(sampler), ray.time);
+                    let pdf_val = scattering.value(scattered.dir);
+                    if pdf_val <= 0.0 {
+                        break;
+                    }
+
+                    throughput *=
+                        attenuation * hit.material.scattering_pdf(&ray, &hit, &scattered) / pdf_val;
+                    ray = scattered;
+                }
+            }
+
+            if !russian_roulette(depth, self.rr_depth, &mut throughput, sampler) {
+                break;
+            }
+        }
+
+        radiance
+    }
+}
+
+// Path tracer with next event estimation. Every diffuse bounce takes one sample towards the
+// lights list and one from the material, and both are weighted with multiple importance
+// sampling, so neither small bright lights nor glossy reflections of big ones get noisy.
+// Specular bounces can't be aimed at lights and count whatever they hit in full.
+pub struct MisIntegrator {
+    pub max_depth: u32,
+    pub rr_depth: u32,
+    pub heuristic: MisHeuristic,
+}
+
+impl MisIntegrator {
+    pub fn new(max_depth: u32, rr_depth: u32, heuristic: MisHeuristic) -> Self {
+        Self {
+            max_depth,
+            rr_depth,
+            heuristic,
+        }
+    }
+}
+
+impl Integrator for MisIntegrator {
+    fn li(
+        &self,
+        mut ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Radiance {
+        let mut radiance = Radiance::default();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        // origin and material pdf of the last diffuse bounce, None after specular ones
+        let mut last_scatter: Option<(Point3, f32)> = None;
+
+        for depth in 0..self.max_depth {
+            let hit = match scene.hit(&ray, RayKind::path(depth)) {
+                Some(hit) => hit,
+                None => {
+                    radiance.add(depth, throughput * scene.background);
+                    break;
+                }
+            };
+
+            let emitted = hit.material.emitted(&ray, &hit);
+            if !emitted.near_zero() {
+                let weight = match last_scatter {
+                    Some((orig, material_pdf)) => self
+                        .heuristic
+                        .weight(material_pdf, scene.light_pdf(orig, ray.dir)),
+                    None => 1.0,
+                };
+                radiance.add(depth, weight * throughput * emitted);
+            }
+
+            let (reflection_pdf, attenuation) = match hit.material.scatter(&ray, &hit, sampler) {
+                None => break,
+                Some(ReflectionRecord::Specular {
+                    specular_ray,
+                    attenuation,
+                }) => {
+                    throughput *= attenuation;
+                    ray = specular_ray;
+                    last_scatter = None;
+
+                    if !russian_roulette(depth, self.rr_depth, &mut throughput, sampler) {
+                        break;
+                    }
+                    continue;
+                }
+                Some(ReflectionRecord::Scatter { pdf, attenuation }) => (pdf, attenuation),
+            };
+
+            // light sample
+            if !scene.lights.objects.is_empty() {
+                let to_light = Ray::new(hit.p, scene.lights.random(hit.p, sampler), ray.time);
+                let light_pdf = scene.light_pdf(hit.p, to_light.dir);
+
+                if light_pdf > 0.0 {
+                    if let Some(light_hit) = scene.hit(&to_light, RayKind::Shadow) {
+                        let light = light_hit.material.emitted(&to_light, &light_hit);
+                        let f = attenuation * hit.material.scattering_pdf(&ray, &hit, &to_light);
+                        let weight = self
+                            .heuristic
+                            .weight(light_pdf, reflection_pdf.value(to_light.dir));
+
+                        radiance.add(depth + 1, weight * throughput * f * light / light_pdf);
+                    }
+                }
+            }
+
+            // material sample, its light contribution gets weighted on the next hit
+            let scattered = Ray::new(hit.p, reflection_pdf.generate(sampler), ray.time);
+            let material_pdf = reflection_pdf.value(scattered.dir);
+            if material_pdf <= 0.0 {
+                break;
+            }
+
+            throughput *=
+                attenuation * hit.material.scattering_pdf(&ray, &hit, &scattered) / material_pdf;
+            ray = scattered;
+            last_scatter = Some((hit.p, material_pdf));
+
+            if !russian_roulette(depth, self.rr_depth, &mut throughput, sampler) {
+                break;
+            }
+        }
+
+        radiance
+    }
+}
+
+// A ray leaving a random point of the lights list, for integrators that trace light
+// forwards. Lights emit from both sides, so the direction is cosine distributed about a
+// randomly picked side.
+pub struct LightSample<'a> {
+    pub hit: HitRecord<'a>,
+    pub ray: Ray,
+    pub radiance: Color,
+    // area density of the point and solid angle density of the direction
+    pub pdf_pos: f32,
+    pub pdf_dir: f32,
+}
+
+pub fn sample_emission<'a>(
+    scene: &SceneView<'a>,
+    time: f32,
+    sampler: &mut dyn Sampler,
+) -> Option<LightSample<'a>> {
+    let (hit, pdf_pos) = scene.lights.sample_surface(sampler)?;
+    let side = if sampler.get_1d() < 0.5 {
+        hit.normal
+    } else {
+        -hit.normal
+    };
+    let dir = ONB::build_from_w(side).local_vec3(Vec3::sample_cosine_dir(sampler.get_2d()));
+    let pdf_dir = emission_pdf(&hit, hit.p + dir);
+    let radiance = emission(&hit, hit.p + dir, time);
+
+    if pdf_pos <= 0.0 || pdf_dir <= 0.0 || radiance.near_zero() {
+        return None;
+    }
+
+    Some(LightSample {
+        hit,
+        ray: Ray::new(hit.p, dir, time),
+        radiance,
+        pdf_pos,
+        pdf_dir,
+    })
+}
+
+// radiance the surface at `hit` emits towards `to`
+pub fn emission(hit: &HitRecord, to: Point3, time: f32) -> Color {
+    let ray = Ray::new(to, hit.p - to, time);
+    let mut hit = *hit;
+    hit.set_face_normal(&ray, hit.normal);
+    hit.material.emitted(&ray, &hit)
+}
+
+// solid angle density of sample_emission() leaving `hit` towards `to`
+pub fn emission_pdf(hit: &HitRecord, to: Point3) -> f32 {
+    hit.normal.dot((to - hit.p).unit_vector()).abs() / (2.0 * PI)
+}
+
+// Once a path is `rr_depth` bounces deep, kills it with a probability based on its
+// throughput. Survivors are reweighted so the estimate stays unbiased.
+pub fn russian_roulette(
+    depth: u32,
+    rr_depth: u32,
+    throughput: &mut Color,
+    sampler: &mut dyn Sampler,
+) -> bool {
+    if depth + 1 < rr_depth {
+        return true;
+    }
+
+    let p = throughput.x.max(throughput.y).max(throughput.z).min(1.0);
+    if p <= 0.0 || p.is_nan() || sampler.get_1d() >= p {
+        false
+    } else {
+        *throughput /= p;
+        true
+    }
+}
@@ -0,0 +1,344 @@
+use crate::material::refract;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+use std::fs;
+use std::path::Path;
+
+// Lens tables are written in millimetres, scenes are in metres.
+const MM: f32 = 0.001;
+
+// One surface of a lens, listed from the front of the lens (the scene side) to the back.
+// In lens space the film is at z = 0 and the lens stretches along -z towards the scene.
+#[derive(Debug, Clone, Copy)]
+pub struct LensElement {
+    // radius of curvature, positive when the surface bulges towards the scene and 0 for
+    // the aperture stop, which is flat
+    pub radius: f32,
+    // distance along the axis to the next surface, or to the film after the last one
+    pub thickness: f32,
+    // index of refraction of what lies behind the surface, 1 for air
+    pub eta: f32,
+    pub aperture_radius: f32,
+}
+
+impl LensElement {
+    pub fn is_stop(&self) -> bool {
+        self.radius == 0.0
+    }
+}
+
+// A range of positions on the plane of the rear element.
+#[derive(Debug, Clone, Copy)]
+pub struct PupilBounds {
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+impl PupilBounds {
+    fn empty() -> Self {
+        Self {
+            min: (f32::INFINITY, f32::INFINITY),
+            max: (f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    fn expand(&mut self, x: f32, y: f32) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+
+    pub fn area(&self) -> f32 {
+        (self.max.0 - self.min.0).max(0.0) * (self.max.1 - self.min.1).max(0.0)
+    }
+
+    pub fn lerp(&self, u: (f32, f32)) -> (f32, f32) {
+        (
+            self.min.0 + u.0 * (self.max.0 - self.min.0),
+            self.min.1 + u.1 * (self.max.1 - self.min.1),
+        )
+    }
+}
+
+// A lens made of spherical elements and an aperture stop, as patents and lens design books
+// tabulate them.
+#[derive(Debug, Clone)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    // One element per line: radius of curvature, thickness, index of refraction and
+    // aperture diameter, all lengths in millimetres, the same layout pbrt reads. The stop
+    // has radius 0, an index of 0 or 1 means air, and # starts a comment.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut elements = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+            if values.len() != 4 {
+                return Err(format!(
+                    "line {}: expected 4 numbers, got {}",
+                    i + 1,
+                    values.len()
+                ));
+            }
+
+            elements.push(LensElement {
+                radius: values[0] * MM,
+                thickness: values[1] * MM,
+                eta: if values[2] == 0.0 { 1.0 } else { values[2] },
+                aperture_radius: values[3] * MM / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err("no lens elements".to_string());
+        }
+        if elements.iter().filter(|e| e.is_stop()).count() > 1 {
+            return Err("more than one aperture stop".to_string());
+        }
+
+        Ok(Self { elements })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Opens or closes the stop to `diameter` millimetres, it can't open past its size in
+    // the table.
+    pub fn set_aperture(&mut self, diameter: f32) {
+        for element in self.elements.iter_mut().filter(|e| e.is_stop()) {
+            let radius = diameter * MM / 2.0;
+            if radius > element.aperture_radius {
+                eprintln!(
+                    "The aperture can only open to {} mm!",
+                    2.0 * element.aperture_radius / MM
+                );
+            }
+            element.aperture_radius = radius.min(element.aperture_radius);
+        }
+    }
+
+    pub fn rear_z(&self) -> f32 {
+        -self.elements.last().map_or(0.0, |e| e.thickness)
+    }
+
+    pub fn front_z(&self) -> f32 {
+        -self.elements.iter().map(|e| e.thickness).sum::<f32>()
+    }
+
+    pub fn rear_radius(&self) -> f32 {
+        self.elements.last().map_or(0.0, |e| e.aperture_radius)
+    }
+
+    // Follows a ray leaving the film through the lens, None if an element or the stop
+    // blocks it or it reflects inside the glass. The ray comes out in lens space.
+    pub fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = ray.clone();
+        let mut z = 0.0;
+
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+            z -= element.thickness;
+            ray = self.pass(ray, element, z)?;
+
+            if !element.is_stop() {
+                let eta_out = if i > 0 { self.elements[i - 1].eta } else { 1.0 };
+                ray.dir = self.bend(&ray, element, z, element.eta / eta_out)?;
+            }
+        }
+
+        Some(ray)
+    }
+
+    // The same coming from the scene, for working out where the lens focuses.
+    pub fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = ray.clone();
+        let mut z = self.front_z();
+
+        for i in 0..self.elements.len() {
+            let element = &self.elements[i];
+            ray = self.pass(ray, element, z)?;
+
+            if !element.is_stop() {
+                let eta_in = if i > 0 { self.elements[i - 1].eta } else { 1.0 };
+                ray.dir = self.bend(&ray, element, z, eta_in / element.eta)?;
+            }
+            z += element.thickness;
+        }
+
+        Some(ray)
+    }
+
+    // moves the ray to where it meets the surface whose vertex is at `z`, None if it
+    // misses or lands outside the aperture
+    fn pass(&self, mut ray: Ray, element: &LensElement, z: f32) -> Option<Ray> {
+        let t = if element.is_stop() {
+            if ray.dir.z == 0.0 {
+                return None;
+            }
+            (z - ray.orig.z) / ray.dir.z
+        } else {
+            intersect_spherical(element.radius, z + element.radius, &ray)?
+        };
+        if t <= 0.0 {
+            return None;
+        }
+
+        let p = ray.at(t);
+        if p.x * p.x + p.y * p.y > element.aperture_radius * element.aperture_radius {
+            return None;
+        }
+
+        ray.orig = p;
+        Some(ray)
+    }
+
+    // the ray's new direction after refracting at the surface it sits on
+    fn bend(&self, ray: &Ray, element: &LensElement, z: f32, ni_over_nt: f32) -> Option<Vec3> {
+        let center = Point3::new(0.0, 0.0, z + element.radius);
+        let mut normal = (ray.orig - center).unit_vector();
+        if normal.dot(ray.dir) > 0.0 {
+            normal = -normal;
+        }
+
+        refract(ray.dir, normal, ni_over_nt)
+    }
+
+    // Focal length and where the principal planes are, from a thick lens approximation:
+    // a ray parallel to the axis crosses it at the focal point on the other side, and
+    // seems to bend at the principal plane on that side. Returns the focal length, the
+    // scene side principal plane and the film side one, None if the rays don't get through.
+    pub fn thick_lens(&self) -> Option<(f32, f32, f32)> {
+        // close enough to the axis for the paraxial approximation
+        let x = 0.01 * self.stop_radius();
+
+        let from_scene = Ray::new(
+            Point3::new(x, 0.0, self.front_z() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+        let out = self.trace_from_scene(&from_scene)?;
+        let (film_focus, film_plane) = cardinal_points(x, &out)?;
+
+        let from_film = Ray::new(Point3::new(x, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let out = self.trace_from_film(&from_film)?;
+        let (_, scene_plane) = cardinal_points(x, &out)?;
+
+        Some((film_focus - film_plane, scene_plane, film_plane))
+    }
+
+    // Moves the film so things `distance` in front of it are sharp, which also changes
+    // the field of view. Too close to focus on, it focuses as close as it can.
+    pub fn focus(&mut self, distance: f32) -> Result<(), String> {
+        let (focal_length, scene_plane, film_plane) = self
+            .thick_lens()
+            .ok_or("rays along the axis don't make it through the lens")?;
+
+        // With the film moved to z, the object distance from the scene side principal
+        // plane and the image distance from the film side one sum to the same `span`
+        // wherever z is. The thin lens equation then makes them the roots of
+        // x^2 - span x + f span, the image distance is the small one.
+        let span = scene_plane - film_plane + distance;
+        let discriminant = span * (span - 4.0 * focal_length);
+        if discriminant < 0.0 {
+            eprintln!("The lens can't focus as close as {}!", distance);
+        }
+        let image_distance = 0.5 * (span - discriminant.max(0.0).sqrt());
+        let film_z = film_plane + image_distance;
+
+        let last = self.elements.len() - 1;
+        self.elements[last].thickness += film_z;
+        if self.elements[last].thickness <= 0.0 {
+            return Err("the film ends up inside the lens".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn stop_radius(&self) -> f32 {
+        self.elements
+            .iter()
+            .find(|e| e.is_stop())
+            .unwrap_or(&self.elements[0])
+            .aperture_radius
+    }
+
+    // Where on the rear element rays from film points `r0` to `r1` off the axis along +x
+    // get through the lens, found by tracing a grid of rays at it. Sampling just that part
+    // of the rear element wastes far fewer rays towards the corners of the image.
+    pub fn exit_pupil_bounds(&self, r0: f32, r1: f32) -> PupilBounds {
+        const GRID: usize = 128;
+        let half = 1.5 * self.rear_radius();
+        let step = 2.0 * half / GRID as f32;
+        let mut bounds = PupilBounds::empty();
+
+        for i in 0..GRID * GRID {
+            let r = r0 + (r1 - r0) * (i as f32 + 0.5) / (GRID * GRID) as f32;
+            let film = Point3::new(r, 0.0, 0.0);
+            let x = -half + step * ((i % GRID) as f32 + 0.5);
+            let y = -half + step * ((i / GRID) as f32 + 0.5);
+            let rear = Point3::new(x, y, self.rear_z());
+
+            if self
+                .trace_from_film(&Ray::new(film, rear - film, 0.0))
+                .is_some()
+            {
+                bounds.expand(x, y);
+            }
+        }
+
+        // the grid can step over a thin sliver of the pupil
+        if bounds.area() > 0.0 {
+            bounds.min = (bounds.min.0 - step, bounds.min.1 - step);
+            bounds.max = (bounds.max.0 + step, bounds.max.1 + step);
+        }
+        bounds
+    }
+}
+
+// Where a ray that entered at height `x` parallel to the axis crosses it, and where it is
+// back at height `x`. The ray has come out of the lens in the xz plane.
+fn cardinal_points(x: f32, ray: &Ray) -> Option<(f32, f32)> {
+    if ray.dir.x == 0.0 {
+        return None;
+    }
+
+    let focus = ray.orig.z - ray.orig.x / ray.dir.x * ray.dir.z;
+    let plane = ray.orig.z + (x - ray.orig.x) / ray.dir.x * ray.dir.z;
+    Some((focus, plane))
+}
+
+// The distance along `ray` to a sphere of `radius` centred on the axis at `z_center`,
+// picking the hit on the side of the sphere the lens surface is on.
+fn intersect_spherical(radius: f32, z_center: f32, ray: &Ray) -> Option<f32> {
+    let o = ray.orig - Point3::new(0.0, 0.0, z_center);
+    let a = ray.dir.length_squared();
+    let b = 2.0 * ray.dir.dot(o);
+    let c = o.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let t0 = (-b - root) / (2.0 * a);
+    let t1 = (-b + root) / (2.0 * a);
+    // a surface bulging towards the scene is the near side of its sphere for rays heading
+    // to the film and the far side for rays leaving it, the other way round if it bulges
+    // towards the film
+    let closer = (ray.dir.z > 0.0) != (radius < 0.0);
+    Some(if closer { t0 } else { t1 })
+}
@@ -1,200 +1,399 @@
 pub mod aabb;
 pub mod aarect;
+pub mod aov;
+pub mod bdpt;
 pub mod bvh;
 #[allow(dead_code)]
 pub mod camera;
+pub mod checkpoint;
+pub mod cli;
+pub mod debug;
+pub mod denoise;
+pub mod exr;
+pub mod film;
+pub mod filter;
 pub mod gltf;
 pub mod hittable;
+pub mod integrator;
+pub mod lens;
 pub mod material;
 pub mod matrix4;
 pub mod onb;
+pub mod output;
 pub mod pdf;
 pub mod perlin;
+pub mod ppm;
 pub mod ray;
+pub mod render;
+pub mod sampler;
 pub mod scenes;
 pub mod sphere;
+pub mod stats;
 pub mod texture;
+pub mod tonemap;
 pub mod transforms;
 pub mod triangle;
 pub mod vec3;
 
-use hittable::*;
-use material::*;
-use pdf::*;
-use ray::Ray;
-use vec3::*;
-
-use std::mem;
-use std::ptr;
-
-use rayon::prelude::*;
-use std::sync::{Arc, Mutex};
-
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::LineWriter;
-
-const ASPECT_RATIO: f32 = 1.0;
-const NX: usize = 500;
-const NY: usize = (NX as f32 / ASPECT_RATIO) as usize;
-const SAMPLES_PER_PIXEL: usize = 100;
-const MAX_DEPTH: i32 = 50;
-
-// assumes constructor will never panic. we're safe using just Box::new()
-macro_rules! make_array {
-    ($constructor:expr; $n:expr) => {{
-        let mut items: [_; $n] = mem::MaybeUninit::uninit().assume_init();
-        for place in items.iter_mut() {
-            ptr::write(place, $constructor);
+use integrator::*;
+
+use aov::Aov;
+use camera::StereoLayout;
+use checkpoint::Checkpoint;
+use cli::{Command, Settings};
+use film::{Film, PixelSamples};
+use vec3::Color;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::path::Path;
+use std::time::Instant;
+
+fn main() -> std::io::Result<()> {
+    let settings = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Command::Render(settings)) => *settings,
+        Ok(Command::ListScenes) => {
+            for (name, _) in scenes::SCENES {
+                println!("{}", name);
+            }
+            return Ok(());
         }
-        items
-    }};
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(threads) = settings.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("failed to build the rayon thread pool");
+    }
+
+    render(&settings)
 }
 
-fn main() -> std::io::Result<()> {
-    let (world, cam, background, lights) = scenes::cornell_box(ASPECT_RATIO);
-
-    eprintln!("Rendering!");
-
-    // deterministic and low-discrepancy sequence for MC sims
-    let hx = halton::Sequence::new(2)
-        .map(|x| x as f32)
-        .take(SAMPLES_PER_PIXEL)
-        .collect::<Vec<f32>>();
-    let hy = halton::Sequence::new(3)
-        .map(|x| x as f32)
-        .take(SAMPLES_PER_PIXEL)
-        .collect::<Vec<f32>>();
-
-    /*
-    if world.len() == 1 {
-        println!("P3\n{} {}\n255", NX, NY);
-        (0..NY).into_par_iter().rev().for_each(|y| {
-            eprintln!("Scanlines remaining: {}", y);
-            for x in 0..NX {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-
-                for i in 0..SAMPLES_PER_PIXEL {
-                    let u = (x as f32 + hx[i]) / (NX - 1) as f32;
-                    let v = (y as f32 + hy[i]) / (NY - 1) as f32;
-
-                    let r = cam.get_ray(u, v);
-                    pixel_color += ray_color(r, background, &world[0], &lights[0], MAX_DEPTH);
-                }
+fn render(settings: &Settings) -> std::io::Result<()> {
+    let nx = settings.width;
+    let ny = settings.height;
+    let samples_per_pixel = settings.samples_per_pixel;
 
-                image.lock().unwrap()[y as usize][x as usize] =
-                    Vec3::calc_color(pixel_color, SAMPLES_PER_PIXEL);
-            }
-        });
-
-        eprintln!("Outputting image!");
-        let img = image.lock().unwrap();
-        for y in (0..img.len()).rev() {
-            for x in 0..img[y].len() {
-                println!(
-                    "{} {} {}",
-                    img[y][x].x as u8, img[y][x].y as u8, img[y][x].z as u8
-                );
-            }
+    let scene = scenes::by_name(&settings.scene).expect("scene was validated by the cli");
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    let (world, mut cam, background, lights) = scene(settings.aspect_ratio(), &mut rng);
+
+    eprintln!("Rendering {} at {}x{}!", settings.scene, nx, ny);
+
+    // without --progressive, --adaptive or a budget the whole frame is a single pass
+    let pass_samples = settings.pass_samples();
+    let film_aovs = settings.film_aovs();
+    let filter = settings.filter();
+    let passes = samples_per_pixel.div_ceil(pass_samples);
+
+    let mut resume = None;
+    if let Some(checkpoint) = settings.checkpoint.as_ref().filter(|_| settings.resume) {
+        if checkpoint.exists() {
+            let state = Checkpoint::load(checkpoint)?;
+            state.check(settings)?;
+            eprintln!(
+                "Resuming frame {} at {} spp from {}!",
+                state.frame,
+                state.next_sample,
+                checkpoint.display()
+            );
+            resume = Some(state);
         }
-    } else {
-    */
-    for frame in 0..world.len() {
-        let image = unsafe {
-            Arc::new(Mutex::new(Box::new(
-                make_array!( Box::new([Vec3::new_empty(); NX]); NY ),
-            )))
+    }
+
+    let first_frame = resume.as_ref().map_or(0, |state| state.frame);
+    if first_frame >= world.len() {
+        eprintln!("Every frame of the checkpoint is already rendered!");
+    }
+
+    let mut integrator: Box<dyn Integrator> = match (settings.debug, settings.integrator) {
+        (Some(view), _) => Box::new(debug::DebugIntegrator::new(view)),
+        (None, IntegratorKind::Path) => Box::new(PathIntegrator::new(settings.max_depth, settings.rr_depth)),
+        (None, IntegratorKind::Mis) => Box::new(MisIntegrator::new(
+            settings.max_depth,
+            settings.rr_depth,
+            settings.mis_heuristic,
+        )),
+        (None, IntegratorKind::Bdpt) => Box::new(bdpt::BdptIntegrator::new(
+            settings.max_depth,
+            settings.rr_depth,
+            settings.mis_heuristic,
+        )),
+        (None, IntegratorKind::Ppm) => Box::new(ppm::PpmIntegrator::new(
+            settings.max_depth,
+            settings.rr_depth,
+            settings.photons,
+            settings.photon_radius,
+            settings.sampler(),
+        )),
+    };
+
+    // the heat view shows the BVH nodes the stats count
+    let heat_view = settings.debug == Some(debug::DebugView::Heat);
+    if settings.stats || settings.stats_json.is_some() || heat_view {
+        stats::enable();
+    }
+
+    let render_start = Instant::now();
+    for frame in first_frame..world.len() {
+        let path = settings.frame_output(frame, world.len());
+        cam.set_frame(frame);
+        let scene_view = SceneView {
+            world: &world[frame],
+            lights: &lights[frame],
+            background,
+            camera: &*cam,
         };
+        let mut state = resume
+            .take()
+            .unwrap_or_else(|| Checkpoint::new(settings, frame));
+        state.film.exposure = cam.exposure();
+        let started = Instant::now();
+        let mut last_snapshot = Instant::now();
+        let mut last_checkpoint = Instant::now();
 
-        (0..NY).into_par_iter().rev().for_each(|y| {
-            eprintln!("Scanlines remaining: {}", y);
-            for x in 0..NX {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+        while state.next_sample < samples_per_pixel {
+            let first_sample = state.next_sample;
+            let samples = pass_samples.min(samples_per_pixel - first_sample);
 
-                for i in 0..SAMPLES_PER_PIXEL {
-                    let u = (x as f32 + hx[i]) / (NX - 1) as f32;
-                    let v = (y as f32 + hy[i]) / (NY - 1) as f32;
+            // the first pass covers every pixel so each one has a variance estimate
+            let active = match settings.adaptive_threshold {
+                Some(threshold) if first_sample > 0 => Some(state.film.unconverged(threshold)),
+                _ => None,
+            };
 
-                    let r = cam.get_ray(u, v);
-                    pixel_color +=
-                        ray_color(r, background, &world[frame], &lights[frame], MAX_DEPTH);
+            if passes > 1 {
+                // budgeted renders don't know how many passes they will get
+                let pass = first_sample / pass_samples + 1;
+                let pass = match settings.target_error {
+                    Some(_) if first_sample > 0 => format!(
+                        "Pass {}, mean relative error {:.4}",
+                        pass,
+                        state.film.mean_relative_error()
+                    ),
+                    _ if settings.has_budget() => format!("Pass {}", pass),
+                    _ => format!("Pass {}/{}", pass, passes),
+                };
+                match &active {
+                    Some(active) => eprintln!(
+                        "{}, {} pixels left",
+                        pass,
+                        active.iter().filter(|&&a| a).count()
+                    ),
+                    None => eprintln!("{}", pass),
                 }
-
-                image.lock().unwrap()[y as usize][x as usize] =
-                    Vec3::calc_color(pixel_color, SAMPLES_PER_PIXEL);
             }
-        });
-
-        eprintln!("Outputting image {}!", frame);
-        let f = File::create(format!("image{:03}.ppm", frame))?;
-        let mut f = LineWriter::new(f);
-        f.write_all(format!("P3\n{} {}\n255\n", NX, NY).as_bytes())?;
-
-        let img = image.lock().unwrap();
-        for y in (0..img.len()).rev() {
-            for x in 0..img[y].len() {
-                f.write_all(
-                    format!(
-                        "{} {} {}\n",
-                        img[y][x].x as u8, img[y][x].y as u8, img[y][x].z as u8
-                    )
-                    .as_bytes(),
-                )?;
+
+            if active.as_ref().is_some_and(|active| !active.contains(&true)) {
+                eprintln!("Every pixel converged at {} spp!", first_sample);
+                break;
             }
-        }
-    }
-    // }
-    Ok(())
-}
 
-fn ray_color(
-    ray: Ray,
-    background: Color,
-    world: &HittableList,
-    lights: &HittableList,
-    depth: i32,
-) -> Color {
-    if depth <= 0 {
-        return Color::new_empty();
-    }
+            let pass_start = Instant::now();
+            integrator.begin_pass(&scene_view, first_sample / pass_samples);
+            // samples reach the pixels within the filter's extent of their own
+            let (tile_size, extent) = (settings.tile_size, filter.extent());
+            let (image, filtered) = render::render_tiles(nx, ny, tile_size, extent, |x, row, tile| {
+                let y = ny - 1 - row;
+                let mut pixel = PixelSamples::new(&state.film);
+                let mut sampler = settings.sampler().create(samples_per_pixel);
+                let stream = ((frame * ny + row) * nx + x) as u64;
 
-    match world.hit(&ray, 0.001, std::f32::INFINITY) {
-        Some(hit) => {
-            let emitted = hit.material.emitted(&ray, &hit);
-
-            if let Some(reflection) = hit.material.scatter(&ray, &hit) {
-                match reflection {
-                    ReflectionRecord::Specular {
-                        specular_ray,
-                        attenuation,
-                    } => {
-                        return attenuation
-                            * ray_color(specular_ray, background, world, &lights, depth - 1);
+                if let Some(active) = &active {
+                    if !active[row * nx + x] {
+                        return pixel;
                     }
+                }
 
-                    ReflectionRecord::Scatter {
-                        pdf: reflection_cosine_pdf,
-                        attenuation,
-                    } => {
-                        let light_pdf = HittablePDF::new(hit.p, lights);
-                        let mixture_pdf = MixturePDF::new(&light_pdf, &*reflection_cosine_pdf);
-
-                        let scattered = Ray::new(hit.p, mixture_pdf.generate(), ray.time);
-                        let pdf_val = mixture_pdf.value(scattered.dir);
-
-                        return emitted
-                            + attenuation
-                                * hit.material.scattering_pdf(&ray, &hit, &scattered)
-                                * ray_color(scattered, background, world, &lights, depth - 2)
-                                / pdf_val;
+                for i in first_sample..first_sample + samples {
+                    sampler.start_sample(stream, i);
+                    let (dx, dy) = sampler.get_2d();
+                    let u = (x as f32 + dx) / nx as f32;
+                    let v = (y as f32 + dy) / ny as f32;
+
+                    // where the camera sees nothing the sample is black
+                    let (r, weight) = match cam.get_ray(u, v, &mut *sampler) {
+                        Some(r) => r,
+                        None => {
+                            let c = Color::new_empty();
+                            pixel.add(c, (x, row), (dx, 1.0 - dy), &filter, tile);
+                            continue;
+                        }
+                    };
+                    let sample_stats = stats::start_sample();
+                    let splats = pixel.splats.len();
+                    let mut radiance =
+                        integrator.li(r.clone(), &scene_view, &mut *sampler, &mut pixel.splats);
+                    // one NaN would blank the whole pixel, drop the sample instead, along with
+                    // what its light subpaths splatted
+                    let valid = radiance.total().is_finite()
+                        && pixel.splats[splats..].iter().all(|s| s.color.is_finite());
+                    sample_stats.finish(valid);
+                    if !valid {
+                        radiance = Radiance::default();
+                        pixel.splats.truncate(splats);
+                    }
+                    radiance = radiance.scaled(weight);
+                    let c = radiance.total();
+                    pixel.add(c, (x, row), (dx, 1.0 - dy), &filter, tile);
+                    if !film_aovs.is_empty() {
+                        let world = scene_view.world;
+                        aov::accumulate(&film_aovs, &mut pixel.aovs, &r, world, &radiance);
                     }
                 }
+
+                pixel
+            });
+
+            state.film.add_pass(&image, &filtered);
+            state.next_sample += samples;
+
+            // stop if the next pass, taking as long as this one, would overrun the budget
+            let pass_time = pass_start.elapsed().as_secs_f32();
+            let out_of_time = settings
+                .time_limit
+                .is_some_and(|limit| started.elapsed().as_secs_f32() + pass_time > limit);
+            let converged = settings
+                .target_error
+                .is_some_and(|target| state.film.mean_relative_error() <= target);
+            let last_pass = state.next_sample == samples_per_pixel || out_of_time || converged;
+            let snapshot_due = settings.pass_samples.is_some()
+                && settings
+                    .snapshot_interval
+                    .is_none_or(|secs| last_snapshot.elapsed().as_secs_f32() >= secs);
+
+            if !last_pass && snapshot_due {
+                eprintln!(
+                    "Writing snapshot {} at {} spp!",
+                    path.display(),
+                    state.next_sample
+                );
+                write_film(&path, &state.film, settings, cam.stereo_layout())?;
+                last_snapshot = Instant::now();
             }
 
-            return emitted;
+            if let Some(checkpoint) = &settings.checkpoint {
+                let checkpoint_due = settings
+                    .checkpoint_interval
+                    .is_none_or(|secs| last_checkpoint.elapsed().as_secs_f32() >= secs);
+
+                if !last_pass && checkpoint_due {
+                    state.save(checkpoint)?;
+                    last_checkpoint = Instant::now();
+                }
+            }
+
+            if converged {
+                eprintln!("Reached the target error at {} spp!", state.next_sample);
+                break;
+            }
+            if out_of_time {
+                eprintln!("Out of time at {} spp!", state.next_sample);
+                break;
+            }
         }
-        None => {
-            return background;
+
+        if settings.has_budget() {
+            eprintln!(
+                "Took {:.1} spp on average, the mean relative error is {:.4}",
+                state.film.mean_samples(),
+                state.film.mean_relative_error()
+            );
+        }
+
+        eprintln!("Outputting image {}!", path.display());
+        write_film(&path, &state.film, settings, cam.stereo_layout())?;
+
+        if let Some(heatmap) = &settings.sample_heatmap {
+            let heatmap = cli::frame_path(heatmap, frame, world.len());
+            eprintln!("Outputting sample heatmap {}!", heatmap.display());
+            let image = state.film.sample_heatmap(state.next_sample);
+            output::write_image(&heatmap, nx, ny, &image, None, settings.bit_depth)?;
         }
+
+        // the frame is on disk, a resume should start on the next one
+        if let Some(checkpoint) = &settings.checkpoint {
+            Checkpoint::new(settings, frame + 1).save(checkpoint)?;
+        }
+    }
+
+    if settings.stats || settings.stats_json.is_some() {
+        let totals = stats::totals();
+        let elapsed = render_start.elapsed();
+        let threads = rayon::current_num_threads();
+        if settings.stats {
+            eprint!("{}", totals.report(elapsed, threads));
+        }
+        if let Some(path) = &settings.stats_json {
+            eprintln!("Outputting statistics {}!", path.display());
+            std::fs::write(path, totals.to_json(elapsed, threads))?;
+        }
+    }
+    Ok(())
+}
+
+// the image, denoised if asked to, and its AOVs
+// `stereo` is how the camera packed its eyes, for --split-stereo
+fn write_film(
+    path: &Path,
+    film: &Film,
+    settings: &Settings,
+    stereo: Option<StereoLayout>,
+) -> std::io::Result<()> {
+    let aov = |aov: Aov| film.aov_image(aov).expect("the film has every aov in the settings");
+
+    let mut image = film.image();
+    if settings.denoise {
+        let guides = denoise::Guides {
+            albedo: aov(Aov::Albedo),
+            normal: aov(Aov::Normal),
+            depth: aov(Aov::Depth),
+        };
+        // the variance is in scene radiance, the image has the camera's exposure applied
+        let variance = (0..film.width * film.height)
+            .map(|i| film.exposure * film.exposure * film.mean_variance(i))
+            .collect::<Vec<_>>();
+        image = denoise::denoise(film.width, film.height, &image, &variance, &guides);
     }
+
+    let aovs = settings
+        .aovs
+        .iter()
+        .map(|&a| (a.name(), aov(a)))
+        .collect::<Vec<_>>();
+    // what the render achieved, budgeted renders stop at varying sample counts
+    let metadata = [
+        ("spp", format!("{:.2}", film.mean_samples())),
+        ("relative_error", format!("{:.6}", film.mean_relative_error())),
+    ];
+    let tone = settings.tone_mapping();
+    let (width, height) = (film.width, film.height);
+    let tone = Some(&tone);
+    output::write_layers(path, width, height, &image, &aovs, &metadata, tone, settings.bit_depth)?;
+
+    let stereo = match stereo {
+        Some(layout) if settings.split_stereo => layout,
+        _ => return Ok(()),
+    };
+    for (name, right) in [("left", false), ("right", true)] {
+        let rect = stereo.eye_rect(right, width, height);
+        let image = output::crop(&image, width, rect);
+        let aovs = aovs
+            .iter()
+            .map(|(aov, pixels)| (*aov, output::crop(pixels, width, rect)))
+            .collect::<Vec<_>>();
+        let eye_path = output::layer_path(path, name);
+        let [_, _, w, h] = rect;
+        output::write_layers(&eye_path, w, h, &image, &aovs, &metadata, tone, settings.bit_depth)?;
+    }
+    Ok(())
 }
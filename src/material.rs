@@ -1,14 +1,32 @@
 use crate::hittable::HitRecord;
 use crate::pdf::*;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::texture::*;
 use crate::vec3::*;
 
 use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+// Materials are numbered in the order the scene creates them and clones keep the number,
+// so objects sharing a material share it and it is the same from run to run.
+static MATERIALS: AtomicU64 = AtomicU64::new(0);
+
+fn next_id() -> u64 {
+    MATERIALS.fetch_add(1, Ordering::Relaxed)
+}
+
 pub trait Material: Sync + Send {
-    fn scatter(&self, _ray: &Ray, _hr: &HitRecord) -> Option<ReflectionRecord> {
+    // the material's number, for the material debug view
+    fn id(&self) -> u64;
+    // `sampler` is for materials that pick their specular ray at random
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _hr: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ReflectionRecord> {
         None
     }
     fn emitted(&self, _ray: &Ray, _hr: &HitRecord) -> Color {
@@ -17,16 +35,24 @@ pub trait Material: Sync + Send {
     fn scattering_pdf(&self, _ray: &Ray, _hr: &HitRecord, _scattered: &Ray) -> f32 {
         0.0
     }
+    // base colour at the hit, for the albedo debug view
+    fn albedo(&self, _hr: &HitRecord) -> Color {
+        Color::new_empty()
+    }
 }
 
 #[derive(Clone)]
 pub struct Lambertian<A: Texture> {
     pub albedo: A,
+    id: u64,
 }
 
 impl<A: Texture> Lambertian<A> {
     pub fn new(albedo: A) -> Self {
-        Self { albedo }
+        Self {
+            albedo,
+            id: next_id(),
+        }
     }
 }
 
@@ -42,7 +68,16 @@ pub enum ReflectionRecord {
 }
 
 impl<A: Texture> Material for Lambertian<A> {
-    fn scatter(&self, _ray: &Ray, hr: &HitRecord) -> Option<ReflectionRecord> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        hr: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ReflectionRecord> {
         Some(ReflectionRecord::Scatter {
             pdf: Arc::new(CosinePDF::new(hr.normal)),
             attenuation: self.albedo.value(hr.u, hr.v, hr.p),
@@ -54,12 +89,17 @@ impl<A: Texture> Material for Lambertian<A> {
         let cosine = hr.normal.dot(scattered.dir.unit_vector()).max(0.0);
         cosine / PI
     }
+
+    fn albedo(&self, hr: &HitRecord) -> Color {
+        self.albedo.value(hr.u, hr.v, hr.p)
+    }
 }
 
 #[derive(Clone)]
 pub struct Metal {
     pub albedo: Color,
     pub fuzz: f32,
+    id: u64,
 }
 
 impl Metal {
@@ -67,15 +107,26 @@ impl Metal {
         Self {
             albedo,
             fuzz: if f < 1.0 { f } else { 1.0 },
+            id: next_id(),
         }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hr: &HitRecord) -> Option<ReflectionRecord> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hr: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ReflectionRecord> {
         let mut reflected = reflect(ray.dir.unit_vector(), hr.normal);
         if self.fuzz > 0.0 {
-            reflected += self.fuzz * Vec3::random_in_unit_sphere()
+            let (u, w) = (sampler.get_2d(), sampler.get_1d());
+            reflected += self.fuzz * Vec3::sample_unit_ball(u, w)
         };
 
         let attenuation = self.albedo;
@@ -89,23 +140,38 @@ impl Material for Metal {
             None
         }
     }
+
+    fn albedo(&self, _hr: &HitRecord) -> Color {
+        self.albedo
+    }
 }
 
 #[derive(Clone)]
 pub struct Dieletric {
     ir: f32,
+    id: u64,
 }
 
 impl Dieletric {
     pub fn new(index_of_refraction: f32) -> Self {
         Self {
             ir: index_of_refraction,
+            id: next_id(),
         }
     }
 }
 
 impl Material for Dieletric {
-    fn scatter(&self, ray: &Ray, hr: &HitRecord) -> Option<ReflectionRecord> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hr: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ReflectionRecord> {
         let outward_normal: Vec3;
         let ni_over_nt: f32;
         let cosine: f32;
@@ -122,7 +188,7 @@ impl Material for Dieletric {
         }
 
         if let Some(refraction) = refract(ray.dir, outward_normal, ni_over_nt) {
-            if rand::random::<f32>() > schlick(cosine, self.ir) {
+            if sampler.get_1d() > schlick(cosine, self.ir) {
                 let refraction = Ray::new(hr.p, refraction, ray.time);
                 return Some(ReflectionRecord::Specular {
                     specular_ray: refraction,
@@ -133,7 +199,7 @@ impl Material for Dieletric {
 
         let reflected = reflect(ray.dir.unit_vector(), hr.normal);
         if reflected.is_nan() {
-            panic!(format!("reflected: {:?}", reflected));
+            panic!("reflected: {:?}", reflected);
         }
 
         let reflected = Ray::new(hr.p, reflected, ray.time);
@@ -143,13 +209,17 @@ impl Material for Dieletric {
             attenuation,
         })
     }
+
+    fn albedo(&self, _hr: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
 }
 
 pub fn reflect(m: Vec3, n: Vec3) -> Vec3 {
     m - 2.0 * m.dot(n) * n
 }
 
-fn refract(v: Vec3, n: Vec3, ni_over_nt: f32) -> Option<Vec3> {
+pub fn refract(v: Vec3, n: Vec3, ni_over_nt: f32) -> Option<Vec3> {
     let uv = v.unit_vector();
     let dt = uv.dot(n);
     let discriminant = 1.0 - ni_over_nt.powi(2) * (1.0 - dt.powi(2));
@@ -169,15 +239,23 @@ fn schlick(cosine: f32, ref_idx: f32) -> f32 {
 #[derive(Clone)]
 pub struct DiffuseLight<A: Texture> {
     emit: A,
+    id: u64,
 }
 
 impl<A: Texture> DiffuseLight<A> {
     pub fn new(emit: A) -> Self {
-        Self { emit }
+        Self {
+            emit,
+            id: next_id(),
+        }
     }
 }
 
 impl<A: Texture> Material for DiffuseLight<A> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
     fn emitted(&self, ray: &Ray, hr: &HitRecord) -> Color {
         if hr.normal.dot(ray.dir) < 0.0 {
             self.emit.value(hr.u, hr.v, hr.p)
@@ -185,6 +263,10 @@ impl<A: Texture> Material for DiffuseLight<A> {
             Color::new_empty()
         }
     }
+
+    fn albedo(&self, hr: &HitRecord) -> Color {
+        self.emit.value(hr.u, hr.v, hr.p)
+    }
 }
 
 /*
@@ -211,17 +293,31 @@ impl<A: Texture> Material for Isotropic<A> {
 
 pub struct Isotropic {
     albedo: Box<dyn Texture>,
+    id: u64,
 }
 
 impl Isotropic {
     pub fn new(albedo: Box<dyn Texture>) -> Self {
-        Self { albedo }
+        Self {
+            albedo,
+            id: next_id(),
+        }
     }
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray: &Ray, hr: &HitRecord) -> Option<ReflectionRecord> {
-        let specular_ray = Ray::new(hr.p, Vec3::random_in_unit_sphere(), ray.time);
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hr: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ReflectionRecord> {
+        let dir = Vec3::sample_unit_vector(sampler.get_2d());
+        let specular_ray = Ray::new(hr.p, dir, ray.time);
         let attenuation = self.albedo.value(hr.u, hr.v, hr.p);
 
         Some(ReflectionRecord::Specular {
@@ -229,4 +325,8 @@ impl Material for Isotropic {
             attenuation,
         })
     }
+
+    fn albedo(&self, hr: &HitRecord) -> Color {
+        self.albedo.value(hr.u, hr.v, hr.p)
+    }
 }
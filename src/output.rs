@@ -0,0 +1,247 @@
+use crate::exr;
+use crate::tonemap::ToneMapping;
+use crate::vec3::*;
+
+use image::hdr::HDREncoder;
+use image::png::PNGEncoder;
+use image::Rgb;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Ppm,
+    Png,
+    Hdr,
+    Exr,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "ppm" => Some(Format::Ppm),
+            "png" => Some(Format::Png),
+            "hdr" => Some(Format::Hdr),
+            "exr" => Some(Format::Exr),
+            _ => None,
+        }
+    }
+
+    // hdr formats store the linear radiance as is, ldr ones get encoded to 8 or 16 bits
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, Format::Hdr | Format::Exr)
+    }
+}
+
+// `pixels` holds the linear average radiance of each pixel, top row first. `tone` turns
+// it into display values for .ppm and .png, hdr formats get it as is. Without one the
+// pixels are data, like sample counts, and ldr formats only clip them to [0, 1].
+pub fn write_image(
+    path: &Path,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+    tone: Option<&ToneMapping>,
+    bit_depth: u8,
+) -> io::Result<()> {
+    write_layers(path, width, height, pixels, &[], &[], tone, bit_depth)
+}
+
+// Writes `pixels` along with named extra buffers. EXR keeps them in the same file as
+// channels like "albedo.R", other formats get a file per layer, see layer_path(). Layers
+// are data, they are only clipped for ldr formats and only EXR keeps negative values.
+// `metadata` goes into the image's header as EXR string attributes, PNG text chunks,
+// Radiance header lines or PPM comments.
+#[allow(clippy::too_many_arguments)]
+pub fn write_layers(
+    path: &Path,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+    layers: &[(&str, Vec<Color>)],
+    metadata: &[(&str, String)],
+    tone: Option<&ToneMapping>,
+    bit_depth: u8,
+) -> io::Result<()> {
+    let format = Format::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported output format: {}", path.display()),
+        )
+    })?;
+
+    if format != Format::Exr {
+        for (name, layer) in layers {
+            write_layers(
+                &layer_path(path, name),
+                width,
+                height,
+                layer,
+                &[],
+                &[],
+                None,
+                bit_depth,
+            )?;
+        }
+    }
+
+    let display = || match tone {
+        Some(tone) => tone.apply(pixels),
+        None => pixels.to_vec(),
+    };
+    let mut f = BufWriter::new(File::create(path)?);
+
+    match format {
+        Format::Ppm => {
+            f.write_all(b"P6\n")?;
+            for (key, value) in metadata {
+                f.write_all(format!("# {} {}\n", key, value).as_bytes())?;
+            }
+            f.write_all(format!("{} {}\n255\n", width, height).as_bytes())?;
+            f.write_all(&encode_8bit(&display()))?;
+        }
+        Format::Png => {
+            let pixels = display();
+            let (data, color) = if bit_depth == 16 {
+                (encode_16bit(&pixels), image::RGB(16))
+            } else {
+                (encode_8bit(&pixels), image::RGB(8))
+            };
+            let mut png = vec![];
+            PNGEncoder::new(&mut png).encode(&data, width as u32, height as u32, color)?;
+            // text chunks go right after the 8 byte signature and the 25 byte IHDR chunk
+            f.write_all(&png[..33])?;
+            for (key, value) in metadata {
+                write_png_text(&mut f, key, value)?;
+            }
+            f.write_all(&png[33..])?;
+        }
+        Format::Hdr => {
+            let data = pixels
+                .iter()
+                .map(|c| {
+                    let c = sanitize(*c);
+                    Rgb([c.x, c.y, c.z])
+                })
+                .collect::<Vec<_>>();
+            let mut hdr = vec![];
+            HDREncoder::new(&mut hdr).encode(&data, width, height)?;
+            // extra header lines go after the "#?RADIANCE" one
+            let first_line = hdr.iter().position(|&b| b == b'\n').map_or(0, |i| i + 1);
+            f.write_all(&hdr[..first_line])?;
+            for (key, value) in metadata {
+                f.write_all(format!("{}={}\n", key, value).as_bytes())?;
+            }
+            f.write_all(&hdr[first_line..])?;
+        }
+        Format::Exr => {
+            let channel = |i: usize| pixels.iter().map(|c| sanitize(*c)[i]).collect();
+            let mut channels = vec![
+                exr::Channel::new("R", channel(0)),
+                exr::Channel::new("G", channel(1)),
+                exr::Channel::new("B", channel(2)),
+            ];
+            for (name, layer) in layers {
+                for (i, suffix) in ["R", "G", "B"].iter().enumerate() {
+                    let data = layer.iter().map(|c| finite(c[i])).collect();
+                    channels.push(exr::Channel::new(&format!("{}.{}", name, suffix), data));
+                }
+            }
+            exr::write(&mut f, width, height, &mut channels, metadata)?;
+        }
+    }
+
+    f.flush()
+}
+
+// `pixels` are display values in [0, 1], anything outside is clipped
+fn encode_8bit(pixels: &[Color]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|c| sanitize(*c).into_iter())
+        .map(|v| (v * 255.0).round() as u8)
+        .collect()
+}
+
+// png stores 16-bit samples big-endian
+fn encode_16bit(pixels: &[Color]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|c| sanitize(*c).into_iter())
+        .flat_map(|v| ((v * 65535.0).round() as u16).to_be_bytes().to_vec())
+        .collect()
+}
+
+// a tEXt chunk: keyword, a zero byte and the text, followed by the CRC of type and data
+fn write_png_text(w: &mut impl Write, key: &str, value: &str) -> io::Result<()> {
+    let mut chunk = b"tEXt".to_vec();
+    chunk.extend_from_slice(key.as_bytes());
+    chunk.push(0);
+    chunk.extend_from_slice(value.as_bytes());
+
+    w.write_all(&((chunk.len() - 4) as u32).to_be_bytes())?;
+    w.write_all(&chunk)?;
+    w.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+// CRC-32 as PNG uses it, a bit at a time since the chunks are tiny
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// the `w` by `h` pixels from column `x` and row `y` of an image `width` wide
+pub fn crop(pixels: &[Color], width: usize, [x, y, w, h]: [usize; 4]) -> Vec<Color> {
+    (y..y + h)
+        .flat_map(|row| &pixels[row * width + x..row * width + x + w])
+        .copied()
+        .collect()
+}
+
+// image.png with a layer named albedo goes to image.albedo.png
+pub fn layer_path(path: &Path, layer: &str) -> PathBuf {
+    stem_suffix(path, &format!(".{}", layer))
+}
+
+// `suffix` added to the end of the file stem, before the extension
+pub fn stem_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}{}", stem, suffix),
+    };
+
+    path.with_file_name(name)
+}
+
+fn sanitize(c: Color) -> Color {
+    Color::new(
+        finite(c.x).max(0.0),
+        finite(c.y).max(0.0),
+        finite(c.z).max(0.0),
+    )
+}
+
+fn finite(v: f32) -> f32 {
+    if v.is_finite() {
+        v
+    } else {
+        0.0
+    }
+}
@@ -1,12 +1,13 @@
 use crate::hittable::*;
 use crate::onb::ONB;
+use crate::sampler::Sampler;
 use crate::vec3::*;
 
 use std::f32::consts;
 
 pub trait PDF {
     fn value(&self, dir: Vec3) -> f32;
-    fn generate(&self) -> Vec3;
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
 }
 
 pub struct CosinePDF {
@@ -31,8 +32,9 @@ impl PDF for CosinePDF {
         }
     }
 
-    fn generate(&self) -> Vec3 {
-        self.uvw.local_vec3(Vec3::random_cosine_dir())
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.uvw
+            .local_vec3(Vec3::sample_cosine_dir(sampler.get_2d()))
     }
 }
 
@@ -55,8 +57,8 @@ impl PDF for HittablePDF<'_> {
         self.hit.pdf_value(self.orig, dir)
     }
 
-    fn generate(&self) -> Vec3 {
-        self.hit.random(self.orig)
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.hit.random(self.orig, sampler)
     }
 }
 
@@ -79,24 +81,23 @@ impl PDF for MixturePDF<'_> {
         0.5 * self.p0.value(dir) + 0.5 * self.p1.value(dir)
     }
 
-    fn generate(&self) -> Vec3 {
-        if rand::random::<f32>() < 0.5 {
-            self.p0.generate()
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.get_1d() < 0.5 {
+            self.p0.generate(sampler)
         } else {
-            self.p1.generate()
+            self.p1.generate(sampler)
         }
     }
 }
 
-pub fn random_to_sphere(radius: f32, distance_squared: f32) -> Vec3 {
-    let r1 = rand::random::<f32>();
-    let r2 = rand::random::<f32>();
+pub fn random_to_sphere(radius: f32, distance_squared: f32, u: (f32, f32)) -> Vec3 {
+    let (r1, r2) = u;
 
     let z = 1.0 + r2 * ((1.0 - radius.powi(2) / distance_squared).sqrt() - 1.0);
     let phi = 2.0 * consts::PI * r1;
 
     let x = phi.cos() * (1.0 - z.powi(2)).sqrt();
-    let y = phi.cos() * (1.0 - z.powi(2)).sqrt();
+    let y = phi.sin() * (1.0 - z.powi(2)).sqrt();
 
     Vec3::new(x, y, z)
 }
@@ -11,16 +11,16 @@ pub struct Perlin {
 }
 
 impl Perlin {
-    pub fn new() -> Self {
+    pub fn new(rng: &mut impl Rng) -> Self {
         let mut ranfloat = vec![];
 
         for _ in 0..POINT_COUNT {
-            ranfloat.push(Vec3::random_range(-1.0, 1.0));
+            ranfloat.push(Vec3::random_range(-1.0, 1.0, rng));
         }
 
-        let perm_x = perlin_generate_perm();
-        let perm_y = perlin_generate_perm();
-        let perm_z = perlin_generate_perm();
+        let perm_x = perlin_generate_perm(rng);
+        let perm_y = perlin_generate_perm(rng);
+        let perm_z = perlin_generate_perm(rng);
 
         Self {
             ranfloat,
@@ -40,10 +40,10 @@ impl Perlin {
         let k = p.z.floor() as usize;
 
         let mut c: [[[Vec3; 3]; 3]; 3] = [[[Vec3::new_empty(); 3]; 3]; 3];
-        for di in 0..2 {
-            for dj in 0..2 {
-                for dk in 0..2 {
-                    c[di][dj][dk] = self.ranfloat[self.perm_x[(i + di) & 0xFF]
+        for (di, plane) in c.iter_mut().enumerate().take(2) {
+            for (dj, row) in plane.iter_mut().enumerate().take(2) {
+                for (dk, corner) in row.iter_mut().enumerate().take(2) {
+                    *corner = self.ranfloat[self.perm_x[(i + di) & 0xFF]
                         ^ self.perm_y[(j + dj) & 0xFF]
                         ^ self.perm_z[(k + dk) & 0xFF]];
                 }
@@ -51,14 +51,15 @@ impl Perlin {
         }
 
         let mut accum = 0.0;
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let weight_v = Vec3::new(u - i as f32, v - j as f32, w - k as f32);
-                    accum += (i as f32 * u + (1.0 - i as f32) * (1.0 - u))
-                        * (j as f32 * v + (1.0 - j as f32) * (1.0 - v))
-                        * (k as f32 * w + (1.0 - k as f32) * (1.0 - w))
-                        * c[i][j][k].dot(weight_v);
+        for (i, plane) in c.iter().enumerate().take(2) {
+            for (j, row) in plane.iter().enumerate().take(2) {
+                for (k, corner) in row.iter().enumerate().take(2) {
+                    let (i, j, k) = (i as f32, j as f32, k as f32);
+                    let weight_v = Vec3::new(u - i, v - j, w - k);
+                    accum += (i * u + (1.0 - i) * (1.0 - u))
+                        * (j * v + (1.0 - j) * (1.0 - v))
+                        * (k * w + (1.0 - k) * (1.0 - w))
+                        * corner.dot(weight_v);
                 }
             }
         }
@@ -80,15 +81,13 @@ impl Perlin {
     }
 }
 
-fn perlin_generate_perm() -> Vec<usize> {
+fn perlin_generate_perm(rng: &mut impl Rng) -> Vec<usize> {
     let mut p = vec![];
 
     for i in 0..POINT_COUNT {
         p.push(i);
     }
 
-    let mut rng = rand::thread_rng();
-
     for i in (1..POINT_COUNT).rev() {
         let target = rng.gen_range(0..i);
         p.swap(i, target)
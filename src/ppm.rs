@@ -0,0 +1,414 @@
+use crate::film::Splat;
+use crate::hittable::*;
+use crate::integrator::*;
+use crate::material::*;
+use crate::onb::ONB;
+use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerConfig};
+use crate::stats::{self, RayKind};
+use crate::vec3::*;
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// how fast the gather radius shrinks, lower keeps more photons per gather for longer
+const ALPHA: f32 = 2.0 / 3.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    pub p: Point3,
+    // direction the photon was travelling in
+    pub dir: Vec3,
+    pub power: Color,
+}
+
+// Photons bucketed in a uniform grid with cells as wide as the gather diameter, so a
+// gather only looks at the cells its sphere overlaps.
+pub struct PhotonMap {
+    radius: f32,
+    photons: Vec<Photon>,
+    cells: HashMap<(i32, i32, i32), (usize, usize)>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>, radius: f32) -> Self {
+        let cell_size = 2.0 * radius;
+        photons.sort_by_key(|photon| cell(photon.p, cell_size));
+
+        let mut cells = HashMap::new();
+        let mut start = 0;
+        for end in 1..=photons.len() {
+            let key = cell(photons[start].p, cell_size);
+            if end == photons.len() || cell(photons[end].p, cell_size) != key {
+                cells.insert(key, (start, end));
+                start = end;
+            }
+        }
+
+        Self {
+            radius,
+            photons,
+            cells,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    // calls `f` with every photon within the gather radius of `p`
+    pub fn for_each_near(&self, p: Point3, mut f: impl FnMut(&Photon)) {
+        let cell_size = 2.0 * self.radius;
+        let offset = Vec3::new(self.radius, self.radius, self.radius);
+        let (x0, y0, z0) = cell(p - offset, cell_size);
+        let (x1, y1, z1) = cell(p + offset, cell_size);
+
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                for z in z0..=z1 {
+                    if let Some(&(start, end)) = self.cells.get(&(x, y, z)) {
+                        for photon in &self.photons[start..end] {
+                            if (photon.p - p).length_squared() <= self.radius * self.radius {
+                                f(photon);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn cell(p: Point3, cell_size: f32) -> (i32, i32, i32) {
+    (
+        (p.x / cell_size).floor() as i32,
+        (p.y / cell_size).floor() as i32,
+        (p.z / cell_size).floor() as i32,
+    )
+}
+
+// Progressive photon mapping in the probabilistic formulation: every pass shoots a new
+// photon map and renders with a gather radius that shrinks from pass to pass, so the
+// average of all passes converges. Camera paths follow specular bounces and stop at the
+// first diffuse surface, which takes its direct light from light sampling and the rest,
+// caustics included, from the photons. Photons come from the lights list and, when the
+// background isn't black, from the sky, aimed at what the camera sees.
+pub struct PpmIntegrator {
+    pub max_depth: u32,
+    pub rr_depth: u32,
+    pub photons: usize,
+    // gather radius of the first pass, picked from the scene size if not given
+    pub initial_radius: Option<f32>,
+    // how photons are sampled, one stream per pass
+    pub sampler: SamplerConfig,
+    map: PhotonMap,
+}
+
+impl PpmIntegrator {
+    pub fn new(
+        max_depth: u32,
+        rr_depth: u32,
+        photons: usize,
+        initial_radius: Option<f32>,
+        sampler: SamplerConfig,
+    ) -> Self {
+        Self {
+            max_depth,
+            rr_depth,
+            photons,
+            initial_radius,
+            sampler,
+            map: PhotonMap::new(vec![], 1.0),
+        }
+    }
+
+    // Follows one photon, storing it at every diffuse surface it reaches after its first
+    // bounce, the direct hits are left to light sampling.
+    fn trace_photon(
+        &self,
+        scene: &SceneView,
+        sky: Option<(Point3, f32)>,
+        time: f32,
+        sampler: &mut dyn Sampler,
+        photons: &mut Vec<Photon>,
+    ) {
+        let use_sky = match sky {
+            Some(_) if scene.lights.objects.is_empty() => true,
+            Some(_) => sampler.get_1d() < 0.5,
+            None => false,
+        };
+        // with both kinds of emitter each gets half the photons
+        let share = if sky.is_some() && !scene.lights.objects.is_empty() {
+            0.5
+        } else {
+            1.0
+        };
+
+        let (mut ray, flux) = if use_sky {
+            let (center, radius) = sky.expect("checked above");
+            sky_photon(scene.background, center, radius, time, sampler)
+        } else {
+            match sample_emission(scene, time, sampler) {
+                Some(sample) => {
+                    let cos = sample.hit.normal.dot(sample.ray.dir).abs();
+                    let flux = sample.radiance * cos / (sample.pdf_pos * sample.pdf_dir);
+                    (sample.ray, flux)
+                }
+                None => return,
+            }
+        };
+        let flux = flux / (share * self.photons as f32);
+        let mut beta = Color::new(1.0, 1.0, 1.0);
+
+        for bounce in 0..self.max_depth {
+            let hit = match scene.hit(&ray, RayKind::Light) {
+                Some(hit) => hit,
+                None => break,
+            };
+
+            match hit.material.scatter(&ray, &hit, sampler) {
+                None => break,
+                Some(ReflectionRecord::Specular {
+                    specular_ray,
+                    attenuation,
+                }) => {
+                    beta *= attenuation;
+                    ray = specular_ray;
+                }
+                Some(ReflectionRecord::Scatter { pdf, attenuation }) => {
+                    if bounce > 0 {
+                        photons.push(Photon {
+                            p: hit.p,
+                            dir: ray.dir.unit_vector(),
+                            power: flux * beta,
+                        });
+                    }
+
+                    let scattered = Ray::new(hit.p, pdf.generate(sampler), ray.time);
+                    let pdf_val = pdf.value(scattered.dir);
+                    if pdf_val <= 0.0 {
+                        break;
+                    }
+
+                    beta *=
+                        attenuation * hit.material.scattering_pdf(&ray, &hit, &scattered) / pdf_val;
+                    ray = scattered;
+                }
+            }
+
+            if !russian_roulette(bounce, self.rr_depth, &mut beta, sampler) {
+                break;
+            }
+        }
+    }
+
+    // light arriving at a diffuse hit straight from the lights list or the background
+    fn direct(
+        &self,
+        scene: &SceneView,
+        ray: &Ray,
+        hit: &HitRecord,
+        scattering: &dyn crate::pdf::PDF,
+        attenuation: Color,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut direct = Color::new_empty();
+
+        if !scene.lights.objects.is_empty() {
+            let to_light = Ray::new(hit.p, scene.lights.random(hit.p, sampler), ray.time);
+            let light_pdf = scene.light_pdf(hit.p, to_light.dir);
+
+            if light_pdf > 0.0 {
+                if let Some(light_hit) = scene.hit(&to_light, RayKind::Shadow) {
+                    let light = light_hit.material.emitted(&to_light, &light_hit);
+                    let f = attenuation * hit.material.scattering_pdf(ray, hit, &to_light);
+                    direct += f * light / light_pdf;
+                }
+            }
+        }
+
+        // the sky can only be found by sampling the material
+        if !scene.background.near_zero() {
+            let to_sky = Ray::new(hit.p, scattering.generate(sampler), ray.time);
+            let pdf = scattering.value(to_sky.dir);
+
+            if pdf > 0.0 && scene.hit(&to_sky, RayKind::Shadow).is_none() {
+                let f = attenuation * hit.material.scattering_pdf(ray, hit, &to_sky);
+                direct += f * scene.background / pdf;
+            }
+        }
+
+        direct
+    }
+
+    // density estimate of the photons around a diffuse hit
+    fn gather(&self, ray: &Ray, hit: &HitRecord, attenuation: Color) -> Color {
+        let mut sum = Color::new_empty();
+
+        self.map.for_each_near(hit.p, |photon| {
+            let incoming = Ray::new(hit.p, -photon.dir, ray.time);
+            let cos = hit.normal.dot(incoming.dir);
+            if cos > 0.0 {
+                let f = attenuation * hit.material.scattering_pdf(ray, hit, &incoming) / cos;
+                sum += f * photon.power;
+            }
+        });
+
+        sum / (PI * self.map.radius * self.map.radius)
+    }
+}
+
+impl Integrator for PpmIntegrator {
+    fn li(
+        &self,
+        mut ray: Ray,
+        scene: &SceneView,
+        sampler: &mut dyn Sampler,
+        _splats: &mut Vec<Splat>,
+    ) -> Radiance {
+        let mut radiance = Radiance::default();
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+
+        for depth in 0..self.max_depth {
+            let hit = match scene.hit(&ray, RayKind::path(depth)) {
+                Some(hit) => hit,
+                None => {
+                    radiance.add(depth, throughput * scene.background);
+                    break;
+                }
+            };
+
+            radiance.add(depth, throughput * hit.material.emitted(&ray, &hit));
+
+            match hit.material.scatter(&ray, &hit, sampler) {
+                None => break,
+                Some(ReflectionRecord::Specular {
+                    specular_ray,
+                    attenuation,
+                }) => {
+                    throughput *= attenuation;
+                    ray = specular_ray;
+                }
+                Some(ReflectionRecord::Scatter { pdf, attenuation }) => {
+                    let direct = self.direct(scene, &ray, &hit, &*pdf, attenuation, sampler);
+                    radiance.add(depth + 1, throughput * direct);
+                    // photons have bounced at least once before landing
+                    radiance.add(depth + 2, throughput * self.gather(&ray, &hit, attenuation));
+                    break;
+                }
+            }
+
+            if !russian_roulette(depth, self.rr_depth, &mut throughput, sampler) {
+                break;
+            }
+        }
+
+        radiance
+    }
+
+    fn begin_pass(&mut self, scene: &SceneView, pass: usize) {
+        let bounds = visible_bounds(scene, self.sampler);
+        let initial_radius = self
+            .initial_radius
+            .unwrap_or_else(|| bounds.map_or(1.0, |(_, radius)| 0.01 * radius));
+
+        let mut radius_squared = initial_radius * initial_radius;
+        for i in 1..=pass {
+            radius_squared *= (i as f32 + ALPHA) / (i as f32 + 1.0);
+        }
+
+        let sky = bounds.filter(|_| !scene.background.near_zero());
+        let (time0, time1) = scene.camera.shutter();
+        let curve = scene.camera.shutter_curve();
+        // far above the streams of the pixels
+        let stream = (1 << 63) | pass as u64;
+        let photons = (0..self.photons)
+            .into_par_iter()
+            .fold(
+                || (Vec::new(), self.sampler.create(self.photons)),
+                |(mut photons, mut sampler), i| {
+                    sampler.start_sample(stream, i);
+                    // A photon can land on any row of a rolling shutter, so they are spread
+                    // evenly over the rows to take up all of their times.
+                    let row = (i as f32 + 0.5) / self.photons as f32;
+                    let when = curve.sample(sampler.get_1d(), row);
+                    let time = time0 + when * (time1 - time0);
+                    self.trace_photon(scene, sky, time, &mut *sampler, &mut photons);
+                    (photons, sampler)
+                },
+            )
+            .map(|(photons, _)| {
+                stats::flush();
+                photons
+            })
+            .reduce(Vec::new, |mut a, mut b| {
+                a.append(&mut b);
+                a
+            });
+
+        self.map = PhotonMap::new(photons, radius_squared.sqrt());
+        eprintln!(
+            "Photon map {} has {} photons, radius {:.3}",
+            pass + 1,
+            self.map.len(),
+            self.map.radius
+        );
+    }
+}
+
+// A photon from the sky, entering along a random direction through a disk that covers the
+// sphere the camera's view fits in.
+fn sky_photon(
+    background: Color,
+    center: Point3,
+    radius: f32,
+    time: f32,
+    sampler: &mut dyn Sampler,
+) -> (Ray, Color) {
+    let dir = Vec3::sample_unit_vector(sampler.get_2d());
+    let disk = ONB::build_from_w(dir);
+    let offset = radius * disk.local_vec3(Vec3::sample_unit_disk(sampler.get_2d()));
+    let orig = center - radius * dir + offset;
+
+    // radiance over the density of the direction (uniform sphere) and the disk point
+    let flux = background * (4.0 * PI) * (PI * radius * radius);
+    (Ray::new(orig, dir, time), flux)
+}
+
+// Bounding sphere of what a coarse grid of camera rays hits, None if they all miss.
+fn visible_bounds(scene: &SceneView, sampler: SamplerConfig) -> Option<(Point3, f32)> {
+    const GRID: usize = 16;
+    let mut sampler = sampler.create(GRID * GRID);
+    let mut min = Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = -min;
+
+    for i in 0..GRID * GRID {
+        let s = ((i % GRID) as f32 + 0.5) / GRID as f32;
+        let t = ((i / GRID) as f32 + 0.5) / GRID as f32;
+        sampler.start_sample(u64::MAX, i);
+        let hit = scene
+            .camera
+            .get_ray(s, t, &mut *sampler)
+            .and_then(|(ray, _)| scene.hit(&ray, RayKind::Camera));
+
+        if let Some(hit) = hit {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(hit.p[axis]);
+                max[axis] = max[axis].max(hit.p[axis]);
+            }
+        }
+    }
+
+    if min.x > max.x {
+        return None;
+    }
+
+    let center = (min + max) / 2.0;
+    let radius = ((max - min).length() / 2.0 * 3.0).max(0.001);
+    Some((center, radius))
+}
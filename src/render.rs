@@ -0,0 +1,123 @@
+use crate::stats;
+
+use rayon::prelude::*;
+use std::ops::AddAssign;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// A rectangle of pixels in raster space (row 0 is the top of the image).
+#[derive(Debug, Clone, Copy)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+pub fn tiles(width: usize, height: usize, tile_size: usize) -> Vec<Tile> {
+    let mut tiles = vec![];
+
+    for y0 in (0..height).step_by(tile_size) {
+        for x0 in (0..width).step_by(tile_size) {
+            tiles.push(Tile {
+                x0,
+                y0,
+                width: tile_size.min(width - x0),
+                height: tile_size.min(height - y0),
+            });
+        }
+    }
+
+    tiles
+}
+
+// What the pixels of a tile add to the pixels around them, out to `margin` pixels past the
+// tile's edges. Parts hanging off the image are dropped in the merge.
+pub struct TileBuffer<S> {
+    x0: isize,
+    y0: isize,
+    width: usize,
+    values: Vec<S>,
+}
+
+impl<S: Copy + Default + AddAssign> TileBuffer<S> {
+    fn new(tile: &Tile, margin: usize) -> Self {
+        let width = tile.width + 2 * margin;
+        let height = tile.height + 2 * margin;
+        Self {
+            x0: tile.x0 as isize - margin as isize,
+            y0: tile.y0 as isize - margin as isize,
+            width,
+            values: vec![S::default(); width * height],
+        }
+    }
+
+    // adds `value` to column `x` of row `y` of the image, which must be within the margin
+    pub fn add(&mut self, x: isize, y: isize, value: S) {
+        let i = (y - self.y0) as usize * self.width + (x - self.x0) as usize;
+        self.values[i] += value;
+    }
+}
+
+// Renders every tile in parallel with `pixel(x, y, buffer)` and stitches them into one
+// row-major buffer. Pixels can also add to their neighbours up to `margin` away through
+// the tile's buffer, those are summed into a second image. Each worker fills buffers it
+// owns, so nothing is shared until the merge, and adds its render statistics to the totals
+// after every tile.
+pub fn render_tiles<T, S, F>(
+    width: usize,
+    height: usize,
+    tile_size: usize,
+    margin: usize,
+    pixel: F,
+) -> (Vec<T>, Vec<S>)
+where
+    T: Clone + Default + Send,
+    S: Copy + Default + AddAssign + Send,
+    F: Fn(usize, usize, &mut TileBuffer<S>) -> T + Sync,
+{
+    let tiles = tiles(width, height, tile_size);
+    let done = AtomicUsize::new(0);
+
+    let rendered = tiles
+        .par_iter()
+        .map(|tile| {
+            let mut buffer = Vec::with_capacity(tile.width * tile.height);
+            let mut around = TileBuffer::new(tile, margin);
+            for y in tile.y0..tile.y0 + tile.height {
+                for x in tile.x0..tile.x0 + tile.width {
+                    buffer.push(pixel(x, y, &mut around));
+                }
+            }
+
+            stats::flush();
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            eprintln!("Tiles done: {}/{}", done, tiles.len());
+
+            (buffer, around)
+        })
+        .collect::<Vec<_>>();
+
+    let mut image = vec![T::default(); width * height];
+    let mut spread = vec![S::default(); width * height];
+    for (tile, (buffer, around)) in tiles.iter().zip(rendered) {
+        for (row, line) in buffer.chunks(tile.width).enumerate() {
+            let start = (tile.y0 + row) * width + tile.x0;
+            image[start..start + tile.width].clone_from_slice(line);
+        }
+
+        for (row, line) in around.values.chunks(around.width).enumerate() {
+            let y = around.y0 + row as isize;
+            if y < 0 || y >= height as isize {
+                continue;
+            }
+            for (column, &value) in line.iter().enumerate() {
+                let x = around.x0 + column as isize;
+                if x >= 0 && x < width as isize {
+                    spread[y as usize * width + x as usize] += value;
+                }
+            }
+        }
+    }
+
+    (image, spread)
+}
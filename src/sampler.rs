@@ -0,0 +1,428 @@
+// Sample generators. Every sample of a pixel is a point in a many dimensional unit cube,
+// handed out one or two dimensions at a time in a fixed order: the position in the pixel,
+// the lens, the time, then whatever the integrator asks for at each bounce. The better the
+// points of a pixel cover the cube, the less noise. Each pixel scrambles its points
+// differently, so neighbouring pixels don't repeat the same pattern.
+//
+// Samplers are deterministic: with the same seed, sample `index` of a stream always gets
+// the same values, whichever thread takes it.
+
+pub trait Sampler: Send {
+    // Starts sample `index` of `stream`. Every pixel is its own stream and takes its
+    // samples in order, from 0 up to the sample count the sampler was made for.
+    fn start_sample(&mut self, stream: u64, index: usize);
+    fn get_1d(&mut self) -> f32;
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+    // a fresh random number for every dimension
+    Independent,
+    // jittered strata in each dimension, shuffled between dimensions
+    Stratified,
+    // radical inverses in prime bases, one base per dimension
+    Halton,
+    // pairs of dimensions from the base 2 Sobol' sequence, shuffled between pairs
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const NAMES: &'static [&'static str] = &["independent", "stratified", "halton", "sobol"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+}
+
+// How halton and sobol points are randomized per pixel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scramble {
+    // the same points in every pixel
+    None,
+    // Cranley-Patterson rotation, a random offset per dimension wrapped around 1
+    Rotation,
+    // nested uniform scrambling of the digits, keeps the points stratified
+    Owen,
+}
+
+impl Scramble {
+    pub const NAMES: &'static [&'static str] = &["none", "rotation", "owen"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scramble::None => "none",
+            Scramble::Rotation => "rotation",
+            Scramble::Owen => "owen",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Scramble::None),
+            "rotation" => Some(Scramble::Rotation),
+            "owen" => Some(Scramble::Owen),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerConfig {
+    pub kind: SamplerKind,
+    pub scramble: Scramble,
+    // picks a different, equally good set of samples
+    pub seed: u64,
+}
+
+impl SamplerConfig {
+    pub fn new(kind: SamplerKind, scramble: Scramble, seed: u64) -> Self {
+        Self {
+            kind,
+            scramble,
+            seed,
+        }
+    }
+
+    // a sampler for streams of `samples` samples each
+    pub fn create(&self, samples: usize) -> Box<dyn Sampler> {
+        let state = State {
+            samples: samples.max(1),
+            seed: self.seed,
+            ..State::default()
+        };
+
+        match self.kind {
+            SamplerKind::Independent => Box::new(IndependentSampler { state }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler { state }),
+            SamplerKind::Halton => Box::new(HaltonSampler {
+                state,
+                scramble: self.scramble,
+            }),
+            SamplerKind::Sobol => Box::new(SobolSampler {
+                state,
+                scramble: self.scramble,
+            }),
+        }
+    }
+}
+
+// Where a sampler is: which sample of which stream, and the next dimension to hand out.
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    samples: usize,
+    seed: u64,
+    stream: u64,
+    index: usize,
+    dimension: u64,
+}
+
+impl State {
+    fn start(&mut self, stream: u64, index: usize) {
+        self.stream = stream;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    // hash of the seed, the stream and the next dimension, then moves on by `dimensions`
+    fn next(&mut self, dimensions: u64) -> u64 {
+        let hash = hash(&[self.seed, self.stream, self.dimension]);
+        self.dimension += dimensions;
+        hash
+    }
+
+    // random bits of the current sample, for `dimension` taken from next()
+    fn random(&self, hash: u64) -> u64 {
+        mix_bits(hash ^ mix_bits(self.index as u64))
+    }
+}
+
+pub struct IndependentSampler {
+    state: State,
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, stream: u64, index: usize) {
+        self.state.start(stream, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let hash = self.state.next(1);
+        unit(self.state.random(hash) as u32)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let hash = self.state.next(2);
+        let bits = self.state.random(hash);
+        (unit(bits as u32), unit((bits >> 32) as u32))
+    }
+}
+
+// Splits each dimension into as many strata as there are samples and gives each sample
+// its own, jittered. The strata are shuffled per dimension so dimensions stay independent.
+pub struct StratifiedSampler {
+    state: State,
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, stream: u64, index: usize) {
+        self.state.start(stream, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let samples = self.state.samples as u32;
+        let hash = self.state.next(1);
+        let stratum = permutation_element(self.state.index as u32 % samples, samples, hash as u32);
+        let jitter = unit(self.state.random(hash) as u32);
+
+        ((stratum as f32 + jitter) / samples as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    // a grid as close to square as the sample count allows
+    fn get_2d(&mut self) -> (f32, f32) {
+        let nx = (self.state.samples as f32).sqrt().ceil() as u32;
+        let ny = (self.state.samples as u32).div_ceil(nx);
+        let hash = self.state.next(2);
+        let stratum =
+            permutation_element(self.state.index as u32 % (nx * ny), nx * ny, hash as u32);
+        let bits = self.state.random(hash);
+
+        let x = ((stratum % nx) as f32 + unit(bits as u32)) / nx as f32;
+        let y = ((stratum / nx) as f32 + unit((bits >> 32) as u32)) / ny as f32;
+        (x.min(ONE_MINUS_EPSILON), y.min(ONE_MINUS_EPSILON))
+    }
+}
+
+// Dimension d is the radical inverse of the sample index in the d-th prime base. Past the
+// last base the dimensions are independent random numbers.
+pub struct HaltonSampler {
+    state: State,
+    scramble: Scramble,
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, stream: u64, index: usize) {
+        self.state.start(stream, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let dimension = self.state.dimension as usize;
+        let hash = self.state.next(1);
+        let index = self.state.index as u64;
+
+        match PRIMES.get(dimension) {
+            None => unit(self.state.random(hash) as u32),
+            Some(&base) => match self.scramble {
+                Scramble::None => radical_inverse(base, index),
+                Scramble::Rotation => {
+                    let v = radical_inverse(base, index) + unit(hash as u32);
+                    if v >= 1.0 {
+                        (v - 1.0).min(ONE_MINUS_EPSILON)
+                    } else {
+                        v
+                    }
+                }
+                Scramble::Owen => owen_scrambled_radical_inverse(base, index, hash as u32),
+            },
+        }
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+// Padded Sobol': every pair of dimensions takes the first two dimensions of the Sobol'
+// sequence, which are stratified in every power of two by power of two grid, with the
+// sample order shuffled per pair so the pairs don't line up with each other.
+pub struct SobolSampler {
+    state: State,
+    scramble: Scramble,
+}
+
+impl SobolSampler {
+    fn scramble(&self, v: u32, seed: u32) -> f32 {
+        let v = match self.scramble {
+            Scramble::None => v,
+            Scramble::Rotation => v.wrapping_add(seed),
+            Scramble::Owen => owen_scramble(v, seed),
+        };
+        unit(v)
+    }
+
+    fn index(&self, hash: u64) -> u32 {
+        let samples = self.state.samples as u32;
+        permutation_element(self.state.index as u32 % samples, samples, hash as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, stream: u64, index: usize) {
+        self.state.start(stream, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let hash = self.state.next(1);
+        let index = self.index(hash);
+        self.scramble(sobol(index, 0), (hash >> 32) as u32)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let hash = self.state.next(2);
+        let index = self.index(hash);
+        let seeds = mix_bits(hash);
+        (
+            self.scramble(sobol(index, 0), seeds as u32),
+            self.scramble(sobol(index, 1), (seeds >> 32) as u32),
+        )
+    }
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// the top 24 bits as a float in [0, 1)
+fn unit(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1u32 << 24) as f32
+}
+
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
+
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e37_79b9_7f4a_7c15, |hash, &v| {
+        mix_bits(hash ^ v.wrapping_add(0x9e37_79b9_7f4a_7c15))
+    })
+}
+
+// Element `i` of a random permutation of 0..n picked by `seed`, without building it.
+// Andrew Kensler, "Correlated Multi-Jittered Sampling".
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+
+    i.wrapping_add(seed) % n
+}
+
+// the digits of `index` in `base` mirrored around the decimal point
+fn radical_inverse(base: u64, mut index: u64) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut reversed = 0;
+    let mut inv_base_n = 1.0;
+
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base + index - next * base;
+        inv_base_n *= inv_base;
+        index = next;
+    }
+
+    ((reversed as f64 * inv_base_n) as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Like radical_inverse(), with every digit permuted by a permutation that depends on the
+// digits before it. Leading zeros get permuted too, down to f32 precision.
+fn owen_scrambled_radical_inverse(base: u64, mut index: u64, seed: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut reversed = 0;
+    let mut inv_base_n = 1.0;
+
+    while inv_base_n > 1e-8 {
+        let next = index / base;
+        let digit_seed = mix_bits(seed as u64 ^ reversed) as u32;
+        let digit = permutation_element((index - next * base) as u32, base as u32, digit_seed);
+        reversed = reversed * base + digit as u64;
+        inv_base_n *= inv_base;
+        index = next;
+    }
+
+    ((reversed as f64 * inv_base_n) as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Dimension 0 or 1 of the Sobol' sequence as 32 bits of fixed point. The first is the van
+// der Corput sequence, the second has the generator matrix of the polynomial x + 1.
+fn sobol(index: u32, dimension: usize) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+
+    let mut v = 1 << 31;
+    let mut result = 0;
+    let mut index = index;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        v ^= v >> 1;
+        index >>= 1;
+    }
+    result
+}
+
+// Owen scrambling of base 2 fixed point: each bit is flipped or not by a hash of the bits
+// above it, so points in the same power of two interval stay in one.
+fn owen_scramble(mut v: u32, seed: u32) -> u32 {
+    if seed & 1 != 0 {
+        v ^= 1 << 31;
+    }
+    for b in 1..32 {
+        let mask = !0u32 << (32 - b);
+        if (mix_bits((v & mask) as u64 ^ seed as u64) as u32) & (1 << b) != 0 {
+            v ^= 1 << (31 - b);
+        }
+    }
+    v
+}
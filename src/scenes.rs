@@ -3,6 +3,7 @@ use crate::bvh::*;
 use crate::camera::*;
 use crate::gltf::GLTF;
 use crate::hittable::*;
+use crate::lens::LensSystem;
 use crate::material::*;
 use crate::matrix4::Matrix4;
 use crate::sphere::*;
@@ -11,10 +12,48 @@ use crate::transforms::*;
 use crate::triangle::Triangle;
 use crate::vec3::*;
 
-use std::sync::Arc;
+use rand::rngs::StdRng;
 use rand::Rng;
+use std::path::Path;
+use std::sync::Arc;
 
-pub fn cornell_box(aspect_ratio: f32) -> (Vec<HittableList>, Camera, Color, Vec<HittableList>) {
+// one world and one list of lights per animation frame, the camera picks the projection
+pub type Scene = (Vec<HittableList>, Box<dyn Camera>, Color, Vec<HittableList>);
+
+// Builds a scene for an aspect ratio. Scenes placing things at random draw from the
+// generator, which is seeded so every run builds the same scene.
+pub type SceneFn = fn(f32, &mut StdRng) -> Scene;
+
+pub const SCENES: &[(&str, SceneFn)] = &[
+    ("cornell_box", cornell_box),
+    ("book2_scene", book2_scene),
+    ("cornell_box_animated", cornell_box_animated),
+    ("simple_light", simple_light),
+    ("first_scene", first_scene),
+    ("first_scene_panorama", first_scene_panorama),
+    ("first_scene_fisheye", first_scene_fisheye),
+    ("first_scene_lens", first_scene_lens),
+    ("first_scene_flythrough", first_scene_flythrough),
+    ("first_scene_stereo", first_scene_stereo),
+    ("first_scene_ods", first_scene_ods),
+    ("first_scene_daylight", first_scene_daylight),
+    ("rolling_shutter", rolling_shutter),
+    ("soft_shutter", soft_shutter),
+    ("engine", engine),
+    ("bokeh", bokeh),
+    ("bokeh_anamorphic", bokeh_anamorphic),
+    ("bokeh_mask", bokeh_mask),
+    ("tilt_shift", tilt_shift),
+];
+
+pub fn by_name(name: &str) -> Option<SceneFn> {
+    SCENES
+        .iter()
+        .find(|(scene_name, _)| *scene_name == name)
+        .map(|(_, scene)| *scene)
+}
+
+pub fn cornell_box(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
     let background = Color::new(0.0, 0.0, 0.0);
     let mut world_vec = vec![];
     let mut lights_vec = vec![];
@@ -32,10 +71,14 @@ pub fn cornell_box(aspect_ratio: f32) -> (Vec<HittableList>, Camera, Color, Vec<
     let mut gltf_import: Vec<Arc<dyn Hittable>> = Vec::new();
 
     eprintln!("materials: {:?}", gltf.materials);
+    // one material for each in the file, shared by its triangles
+    let materials = gltf
+        .materials
+        .iter()
+        .map(|m| Lambertian::new(SolidColorTexture::new(m.albedo)))
+        .collect::<Vec<_>>();
     for mesh in gltf.meshes {
         for indices in mesh.indices.chunks(3) {
-            let gltf_mat = &gltf.materials[mesh.mat_index];
-            let albedo = gltf_mat.albedo;
             eprintln!("{:?}", mesh.transform);
 
             // let (albedo, roughness) = &gltf_mat.metallic_roughness();
@@ -52,7 +95,7 @@ pub fn cornell_box(aspect_ratio: f32) -> (Vec<HittableList>, Camera, Color, Vec<
                 Rotate::new(
                 Rotate::new(
                     Triangle::new(
-                        Lambertian::new(SolidColorTexture::new(albedo)),
+                        materials[mesh.mat_index].clone(),
                         Matrix4::scale(Vec3::new(100.0, 100.0, 100.0))
                             * mesh.transform
                             * mesh.positions[indices[0] as usize],
@@ -139,8 +182,8 @@ pub fn cornell_box(aspect_ratio: f32) -> (Vec<HittableList>, Camera, Color, Vec<
     );
 
     let box1 = Rotate::new(box1, Axis::Y, -18.0);
-    let box1 = Translate::new(box1, Vec3::new(0.0, 0.0, -30.0));
-    // world.push(box1);
+    let _box1 = Translate::new(box1, Vec3::new(0.0, 0.0, -30.0));
+    // world.push(_box1);
 
     /*
     let glass_sphere = Sphere::new(Vec3::new(190.0, 90.0, 190.0), 90.0, Dieletric::new(1.5));
@@ -167,24 +210,13 @@ pub fn cornell_box(aspect_ratio: f32) -> (Vec<HittableList>, Camera, Color, Vec<
     let dist_to_focus = 10.0;
     let aperture = 0.0;
 
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        fov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        0.0,
-        1.0,
-    );
+    let view = View::new(lookfrom, lookat, vup, 0.0, 1.0);
+    let cam = PerspectiveCamera::new(view, fov, aspect_ratio, aperture, dist_to_focus);
 
-    (world_vec, cam, background, lights_vec)
+    (world_vec, Box::new(cam), background, lights_vec)
 }
 
-pub fn book2_scene(
-    aspect_ratio: f32
-) -> (Vec<HittableList>, Camera, Color, Vec<HittableList>) {
+pub fn book2_scene(aspect_ratio: f32, rng: &mut StdRng) -> Scene {
     let mut lights = HittableList::new();
     let mut objects = HittableList::new();
 
@@ -200,7 +232,7 @@ pub fn book2_scene(
             let z0 = -1000.0 + j as f32 * w;
             let y0 = 0.0;
             let x1 = x0 + w;
-            let y1 = rand::random::<f32>() * 100.0;
+            let y1 = rng.gen::<f32>() * 100.0;
             let z1 = z0 + w;
             boxes1.push(Arc::new(RectBox::new(Point3::new(x0, y0, z0), Point3::new(x1, y1, z1), ground.clone())));
         }
@@ -224,7 +256,7 @@ pub fn book2_scene(
     let white = Lambertian::new(SolidColorTexture::new(Color::new(0.73, 0.73, 0.73)));
     let ns = 1000;
     for _ in 0..ns {
-        boxes2.push(Arc::new(Translate::new(Sphere::new(Point3::random_range_i32(0, 165), 10.0, white.clone()), Vec3::new(-100.0, 270.0, 395.0))));
+        boxes2.push(Arc::new(Translate::new(Sphere::new(Point3::random_range_i32(0, 165, rng), 10.0, white.clone()), Vec3::new(-100.0, 270.0, 395.0))));
     }
 
     objects.push(BVH::new(boxes2, 0.0, 1.0));
@@ -238,23 +270,12 @@ pub fn book2_scene(
     let dist_to_focus = 10.0;
     let aperture = 0.0;
 
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        fov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        0.0,
-        1.0,
-    );
-    (vec!(objects), cam, background, vec!(lights))
+    let view = View::new(lookfrom, lookat, vup, 0.0, 1.0);
+    let cam = PerspectiveCamera::new(view, fov, aspect_ratio, aperture, dist_to_focus);
+    (vec!(objects), Box::new(cam), background, vec!(lights))
 }
 
-pub fn cornell_box_animated(
-    aspect_ratio: f32,
-) -> (Vec<HittableList>, Camera, Color, Vec<HittableList>) {
+pub fn cornell_box_animated(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
     let background = Color::new(0.0, 0.0, 0.0);
     let mut world_vec = vec![];
     let mut lights_vec = vec![];
@@ -341,34 +362,25 @@ pub fn cornell_box_animated(
     let dist_to_focus = 10.0;
     let aperture = 0.0;
 
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        fov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        0.0,
-        1.0,
-    );
+    let view = View::new(lookfrom, lookat, vup, 0.0, 1.0);
+    let cam = PerspectiveCamera::new(view, fov, aspect_ratio, aperture, dist_to_focus);
 
-    (world_vec, cam, background, lights_vec)
+    (world_vec, Box::new(cam), background, lights_vec)
 }
 
-pub fn simple_light(aspect_ratio: f32) -> (HittableList, Camera, Color, HittableList) {
+pub fn simple_light(aspect_ratio: f32, rng: &mut StdRng) -> Scene {
     let mut world = HittableList::new();
     let mut lights = HittableList::new();
     let background = Color::new_empty();
 
-    let pertext = NoiseTexture::new(4.0);
+    let pertext = NoiseTexture::new(4.0, rng);
     world.push(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
         Lambertian::new(pertext),
     ));
 
-    let pertext = NoiseTexture::new(4.0);
+    let pertext = NoiseTexture::new(4.0, rng);
     world.push(Sphere::new(
         Point3::new(0.0, 2.0, 0.0),
         2.0,
@@ -402,24 +414,156 @@ pub fn simple_light(aspect_ratio: f32) -> (HittableList, Camera, Color, Hittable
     let dist_to_focus = 10.0;
     let aperture = 0.0;
 
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        fov,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        0.0,
-        1.0,
-    );
+    let view = View::new(lookfrom, lookat, vup, 0.0, 1.0);
+    let cam = PerspectiveCamera::new(view, fov, aspect_ratio, aperture, dist_to_focus);
+
+    (vec![world], Box::new(cam), background, vec![lights])
+}
+
+pub fn first_scene(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let dist_to_focus = 10.0;
+    let aperture = 0.0;
+    let view = first_scene_view();
+    let cam = PerspectiveCamera::new(view, 90.0, aspect_ratio, aperture, dist_to_focus);
+
+    (vec![first_scene_world()], Box::new(cam), first_scene_background(), vec![HittableList::new()])
+}
+
+// first_scene all around the camera, render it twice as wide as high
+pub fn first_scene_panorama(_aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let cam = EquirectangularCamera::new(first_scene_view());
+
+    (vec![first_scene_world()], Box::new(cam), first_scene_background(), vec![HittableList::new()])
+}
+
+pub fn first_scene_fisheye(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let cam = FisheyeCamera::new(first_scene_view(), 180.0, aspect_ratio);
+
+    (vec![first_scene_world()], Box::new(cam), first_scene_background(), vec![HittableList::new()])
+}
+
+// first_scene through a 50 mm double Gauss lens on a full frame sensor, a little
+// stopped down and focused on the middle sphere
+pub fn first_scene_lens(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let lens = LensSystem::load(Path::new("../lenses/dgauss.50mm.dat")).unwrap();
+    let lookfrom = Point3::new(0.0, 0.6, 4.0);
+    let lookat = Point3::new(0.0, 0.0, -1.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let focus_dist = (lookfrom - Point3::new(0.0, 0.0, -0.5)).length();
+
+    let view = View::new(lookfrom, lookat, vup, 0.0, 1.0);
+    let cam = RealisticCamera::new(view, lens, 43.3, aspect_ratio, 8.0, focus_dist).unwrap();
+
+    (vec![first_scene_world()], Box::new(cam), first_scene_background(), vec![HittableList::new()])
+}
+
+// 24 frames swinging round first_scene and zooming in, with a 180 degree shutter so the
+// fast middle of the move blurs.
+pub fn first_scene_flythrough(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let lookat = Point3::new(0.0, 0.0, -1.0);
+    let key = |time: f32, lookfrom: Point3, vfov: f32| {
+        let focus_dist = (lookfrom - lookat).length();
+        CameraKeyframe::new(time, lookfrom, lookat, Vec3::new(0.0, 1.0, 0.0), vfov, focus_dist, 0.0)
+    };
+    let keys = vec![
+        key(0.0, Point3::new(-3.0, 1.0, 2.0), 60.0),
+        key(8.0, Point3::new(0.0, 0.5, 2.5), 50.0),
+        key(16.0, Point3::new(3.0, 1.5, 1.0), 45.0),
+        key(23.0, Point3::new(2.0, 2.5, -3.5), 35.0),
+    ];
+    let cam = AnimatedCamera::new(keys, aspect_ratio, 0.0, 0.5).unwrap();
+
+    let frames = 24;
+    let world = first_scene_world();
+    let lights = vec![HittableList::new(); frames];
+    (vec![world; frames], Box::new(cam), first_scene_background(), lights)
+}
+
+// first_scene side by side for each eye, render it twice as wide as first_scene
+pub fn first_scene_stereo(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let layout = StereoLayout::SideBySide;
+    let cam = StereoCamera::perspective(first_scene_view(), 90.0, aspect_ratio, 0.065, 2.0, layout);
+
+    (vec![first_scene_world()], Box::new(cam), first_scene_background(), vec![HittableList::new()])
+}
+
+// first_scene all around, left eye above the right, render it square
+pub fn first_scene_ods(_aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let cam = StereoCamera::ods(first_scene_view(), 0.065, StereoLayout::TopBottom);
+
+    (vec![first_scene_world()], Box::new(cam), first_scene_background(), vec![HittableList::new()])
+}
+
+// first_scene under a sky as bright as a real one, about 8000 cd/m^2, shot at ISO 100,
+// 1/125 s and f/8 through a lens stopped down to f/8
+pub fn first_scene_daylight(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let mut view = first_scene_view();
+    view.exposure = Some(Exposure::new(100.0, 1.0 / 125.0, 8.0));
+    let mut cam = PerspectiveCamera::new(view, 90.0, aspect_ratio, 0.0, 10.0);
+    cam.set_f_number(8.0);
+    let sky = 8000.0 * first_scene_background();
+
+    (vec![first_scene_world()], Box::new(cam), sky, vec![HittableList::new()])
+}
+
+// A column of balls racing past a camera whose sensor is read out row by row, so it
+// comes out leaning.
+pub fn rolling_shutter(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    // the balls cross the middle halfway through the time, a short exposure stops them
+    // there and the readout spreads the rows out over most of the move
+    racing_balls(aspect_ratio, 0.1, 0.9, ShutterCurve::rolling(0.9).unwrap())
+}
 
-    (world, cam, background, lights)
+// The same balls through a shutter that takes a while to open and close, so their blur
+// fades out at both ends.
+pub fn soft_shutter(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    racing_balls(aspect_ratio, 0.3, 0.7, ShutterCurve::trapezoid(0.4, 0.4).unwrap())
 }
 
-pub fn first_scene(aspect_ratio: f32) -> (Vec<HittableList>, Camera, Color, Vec<HittableList>) {
+fn racing_balls(aspect_ratio: f32, time0: f32, time1: f32, curve: ShutterCurve) -> Scene {
+    let mut world = HittableList::new();
+
+    let ground = Lambertian::new(SolidColorTexture::new(Color::new(0.5, 0.5, 0.5)));
+    world.push(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground));
+
+    let red = Lambertian::new(SolidColorTexture::new(Color::new(0.8, 0.2, 0.1)));
+    for i in 0..8 {
+        let y = 0.25 + 0.5 * i as f32;
+        world.push(MovingSphere::new(
+            Point3::new(-3.0, y, 0.0),
+            Point3::new(3.0, y, 0.0),
+            0.0,
+            1.0,
+            0.25,
+            red.clone(),
+        ));
+    }
+
+    let lookfrom = Point3::new(0.0, 2.0, 10.0);
+    let lookat = Point3::new(0.0, 2.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let mut view = View::new(lookfrom, lookat, vup, time0, time1);
+    view.shutter_curve = curve;
+    let cam = PerspectiveCamera::new(view, 30.0, aspect_ratio, 0.0, 10.0);
+
+    (vec![world], Box::new(cam), Color::new(0.7, 0.8, 1.0), vec![HittableList::new()])
+}
+
+fn first_scene_view() -> View {
+    let lookfrom = Point3::new(0.0, 0.0, 1.0);
+    let lookat = Point3::new(0.0, 0.0, 0.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    View::new(lookfrom, lookat, vup, 0.0, 1.0)
+}
+
+fn first_scene_background() -> Color {
+    Color::new(0.7, 0.8, 1.0)
+}
+
+fn first_scene_world() -> HittableList {
     let mut world = HittableList::new();
-    let background = Color::new(0.7, 0.8, 1.0);
 
     let material_ground = Lambertian::new(SolidColorTexture::new(Color::new(0.8, 0.8, 0.0)));
     let material_center = Lambertian::new(SolidColorTexture::new(Color::new(0.7, 0.3, 0.3)));
@@ -449,25 +593,154 @@ pub fn first_scene(aspect_ratio: f32) -> (Vec<HittableList>, Camera, Color, Vec<
         material_right,
     ));
 
-    let lookfrom = Point3::new(0.0, 0.0, 1.0);
+    world
+}
+
+// A technical view of the engine model: an orthographic camera looking down on it at an
+// angle, framed to fit, under an even grey sky.
+pub fn engine(aspect_ratio: f32, _rng: &mut StdRng) -> Scene {
+    let gltf = GLTF::new("../models/2CylinderEngine.glb".to_string()).unwrap();
+
+    let mut triangles: Vec<Arc<dyn Hittable>> = Vec::new();
+    let mut min = Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = -min;
+    // meshes using the same material in the file share it, the rest share a grey one
+    let materials = gltf
+        .materials
+        .iter()
+        .map(|m| Lambertian::new(SolidColorTexture::new(m.albedo)))
+        .collect::<Vec<_>>();
+    let grey = Lambertian::new(SolidColorTexture::new(Color::new(0.8, 0.8, 0.8)));
+    for mesh in &gltf.meshes {
+        let material = materials.get(mesh.mat_index).unwrap_or(&grey);
+
+        for indices in mesh.indices.chunks(3) {
+            let corner = |i: usize| mesh.transform * mesh.positions[indices[i] as usize];
+            let (a, b, c) = (corner(0), corner(1), corner(2));
+            for p in [a, b, c] {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(p[axis]);
+                    max[axis] = max[axis].max(p[axis]);
+                }
+            }
+            triangles.push(Arc::new(Triangle::new(material.clone(), a, b, c)));
+        }
+    }
+
+    let mut world = HittableList::new();
+    world.push(BVH::new(triangles, 0.0, 1.0));
+
+    let center = (min + max) / 2.0;
+    let size = (max - min).length();
+    let lookfrom = center + size * Vec3::new(1.0, 0.8, 1.0).unit_vector();
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+
+    let view = View::new(lookfrom, center, vup, 0.0, 1.0);
+    let cam = OrthographicCamera::new(view, 0.8 * size, aspect_ratio);
+
+    (vec![world], Box::new(cam), Color::new(0.9, 0.9, 0.9), vec![HittableList::new()])
+}
+
+// A sphere in focus in front of out of focus lights, seen through a seven blade aperture.
+pub fn bokeh(aspect_ratio: f32, rng: &mut StdRng) -> Scene {
+    let shape = ApertureShape::Polygon {
+        blades: 7,
+        rotation: 90.0,
+    };
+    bokeh_scene(aspect_ratio, rng, shape, 1.0)
+}
+
+// the same through a 2x anamorphic lens, the lights turn into tall ovals
+pub fn bokeh_anamorphic(aspect_ratio: f32, rng: &mut StdRng) -> Scene {
+    bokeh_scene(aspect_ratio, rng, ApertureShape::Disk, 2.0)
+}
+
+pub fn bokeh_mask(aspect_ratio: f32, rng: &mut StdRng) -> Scene {
+    let mask = ApertureMask::new(Path::new("../images/aperture_star.png")).unwrap();
+    let shape = ApertureShape::Mask(mask);
+    bokeh_scene(aspect_ratio, rng, shape, 1.0)
+}
+
+fn bokeh_scene(aspect_ratio: f32, rng: &mut StdRng, shape: ApertureShape, squeeze: f32) -> Scene {
+    let mut world = HittableList::new();
+    let mut lights = HittableList::new();
+
+    let ground = Lambertian::new(SolidColorTexture::new(Color::new(0.4, 0.4, 0.4)));
+    world.push(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0, ground));
+    let subject = Lambertian::new(SolidColorTexture::new(Color::new(0.7, 0.3, 0.3)));
+    world.push(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, subject));
+
+    let light = DiffuseLight::new(SolidColorTexture::new(Color::new(4.0, 4.0, 4.0)));
+    world.push(Sphere::new(Point3::new(-2.0, 3.0, 1.0), 1.0, light.clone()));
+    lights.push(Sphere::new(Point3::new(-2.0, 3.0, 1.0), 1.0, light));
+
+    // small bright lights far behind, like a street at night
+    for _ in 0..40 {
+        let center = Point3::new(
+            rng.gen_range(-6.0..6.0),
+            rng.gen_range(0.0..4.0),
+            rng.gen_range(-14.0..-8.0),
+        );
+        let color = Color::new(
+            rng.gen_range(0.5..1.0),
+            rng.gen_range(0.3..0.9),
+            rng.gen_range(0.1..0.6),
+        );
+        let light = DiffuseLight::new(SolidColorTexture::new(40.0 * color));
+        world.push(Sphere::new(center, 0.05, light));
+    }
+
+    let lookfrom = Point3::new(0.0, 0.2, 2.0);
+    let lookat = Point3::new(0.0, 0.0, -1.0);
+    let vup = Vec3::new(0.0, 1.0, 0.0);
+    let dist_to_focus = (lookfrom - lookat).length();
+
+    let view = View::new(lookfrom, lookat, vup, 0.0, 1.0);
+    let mut cam = PerspectiveCamera::new(view, 35.0, aspect_ratio, 0.4, dist_to_focus);
+    cam.aperture_shape = shape;
+    cam.squeeze = squeeze;
+
+    (vec![world], Box::new(cam), Color::new(0.02, 0.02, 0.05), vec![lights])
+}
+
+// A town of boxes seen from above with the plane of focus tilted up against the ground,
+// so only a band through the middle is sharp and it looks like a model.
+pub fn tilt_shift(aspect_ratio: f32, rng: &mut StdRng) -> Scene {
+    let mut world = HittableList::new();
+
+    let ground = Lambertian::new(SolidColorTexture::new(Color::new(0.3, 0.5, 0.2)));
+    world.push(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground));
+
+    let mut houses: Vec<Arc<dyn Hittable>> = Vec::new();
+    for i in -10..10 {
+        for j in -10..10 {
+            let color = Color::new(
+                rng.gen_range(0.3..0.9),
+                rng.gen_range(0.3..0.9),
+                rng.gen_range(0.3..0.9),
+            );
+            let height = rng.gen_range(0.3..1.5);
+            let corner = Point3::new(i as f32 * 1.5, 0.0, j as f32 * 1.5);
+            let material = Lambertian::new(SolidColorTexture::new(color));
+            houses.push(Arc::new(RectBox::new(
+                corner,
+                corner + Vec3::new(1.0, height, 1.0),
+                material,
+            )));
+        }
+    }
+    world.push(BVH::new(houses, 0.0, 1.0));
+
+    let lookfrom = Point3::new(0.0, 12.0, 16.0);
     let lookat = Point3::new(0.0, 0.0, 0.0);
     let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.0;
+    let dist_to_focus = (lookfrom - lookat).length();
 
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        90.0,
-        aspect_ratio,
-        aperture,
-        dist_to_focus,
-        0.0,
-        1.0,
-    );
+    let view = View::new(lookfrom, lookat, vup, 0.0, 1.0);
+    let mut cam = PerspectiveCamera::new(view, 40.0, aspect_ratio, 1.5, dist_to_focus);
+    cam.tilt(-30.0, 0.0);
 
-    (vec![world], cam, background, vec![])
+    (vec![world], Box::new(cam), Color::new(0.7, 0.8, 1.0), vec![HittableList::new()])
 }
 
 /*
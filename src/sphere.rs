@@ -4,6 +4,8 @@ use crate::material::Material;
 use crate::onb::ONB;
 use crate::pdf;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::stats;
 use crate::vec3::*;
 
 use std::f32::consts::{FRAC_PI_2, PI};
@@ -31,7 +33,8 @@ impl<M: Material> Sphere<M> {
 }
 
 impl<M: Sync + Send + Material> Hittable for Sphere<M> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::primitive_test();
         let oc = r.orig - self.center;
         let a = r.dir.length_squared();
         let half_b = oc.dot(r.dir);
@@ -44,9 +47,9 @@ impl<M: Sync + Send + Material> Hittable for Sphere<M> {
         let sqrtd = discriminant.sqrt();
 
         // Find the nearest root that lies in the acceptable range.
-        let root = (-half_b - sqrtd) / a;
+        let mut root = (-half_b - sqrtd) / a;
         if root < t_min || t_max < root {
-            let root = (-half_b + sqrtd) / a;
+            root = (-half_b + sqrtd) / a;
             if root < t_min || t_max < root {
                 return None;
             }
@@ -79,7 +82,10 @@ impl<M: Sync + Send + Material> Hittable for Sphere<M> {
     }
 
     fn pdf_value(&self, orig: Point3, v: Vec3) -> f32 {
-        if let Some(_) = self.hit(&Ray::new(orig, v, 0.0), 0.001, std::f32::INFINITY) {
+        if self
+            .hit(&Ray::new(orig, v, 0.0), 0.001, f32::INFINITY)
+            .is_some()
+        {
             let cos_theta_max =
                 (1.0 - self.radius.powi(2) / (self.center - orig).length_squared()).sqrt();
             let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - cos_theta_max);
@@ -90,11 +96,32 @@ impl<M: Sync + Send + Material> Hittable for Sphere<M> {
         }
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
+    fn random(&self, orig: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
         let dir = self.center - orig;
         let distance_squared = dir.length_squared();
         let onb = ONB::build_from_w(dir);
-        onb.local_vec3(pdf::random_to_sphere(self.radius, distance_squared))
+        onb.local_vec3(pdf::random_to_sphere(
+            self.radius,
+            distance_squared,
+            sampler.get_2d(),
+        ))
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let normal = Vec3::sample_unit_vector(sampler.get_2d());
+        let (u, v) = get_sphere_uv(normal);
+
+        let hr = HitRecord {
+            p: self.center + self.radius * normal,
+            normal,
+            t: 0.0,
+            u,
+            v,
+            front_face: true,
+            material: &self.material,
+        };
+
+        Some((hr, 1.0 / (4.0 * PI * self.radius * self.radius)))
     }
 }
 
@@ -140,7 +167,8 @@ impl<M: Material> MovingSphere<M> {
 }
 
 impl<M: Sync + Send + Material> Hittable for MovingSphere<M> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::primitive_test();
         let oc = r.orig - self.calc_time(r.time);
         let a = r.dir.length_squared();
         let half_b = oc.dot(r.dir);
@@ -153,9 +181,9 @@ impl<M: Sync + Send + Material> Hittable for MovingSphere<M> {
         let sqrtd = discriminant.sqrt();
 
         // Find the nearest root that lies in the acceptable range.
-        let root = (-half_b - sqrtd) / a;
+        let mut root = (-half_b - sqrtd) / a;
         if root < t_min || t_max < root {
-            let root = (-half_b + sqrtd) / a;
+            root = (-half_b + sqrtd) / a;
             if root < t_min || t_max < root {
                 return None;
             }
@@ -195,7 +223,10 @@ impl<M: Sync + Send + Material> Hittable for MovingSphere<M> {
     }
 
     fn pdf_value(&self, orig: Point3, v: Vec3) -> f32 {
-        if let Some(_) = self.hit(&Ray::new(orig, v, 0.0), 0.001, std::f32::INFINITY) {
+        if self
+            .hit(&Ray::new(orig, v, 0.0), 0.001, f32::INFINITY)
+            .is_some()
+        {
             let cos_theta_max =
                 (1.0 - self.radius.powi(2) / (self.center0 - orig).length_squared()).sqrt();
             let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - cos_theta_max);
@@ -206,11 +237,15 @@ impl<M: Sync + Send + Material> Hittable for MovingSphere<M> {
         }
     }
 
-    fn random(&self, orig: Vec3) -> Vec3 {
+    fn random(&self, orig: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
         let dir = self.center0 - orig;
         let distance_squared = dir.length_squared();
         let onb = ONB::build_from_w(dir);
-        onb.local_vec3(pdf::random_to_sphere(self.radius, distance_squared))
+        onb.local_vec3(pdf::random_to_sphere(
+            self.radius,
+            distance_squared,
+            sampler.get_2d(),
+        ))
     }
 }
 
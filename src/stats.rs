@@ -0,0 +1,337 @@
+use crate::hittable::*;
+use crate::ray::Ray;
+
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// camera samples are binned by path length up to this, the last bin takes the longer ones
+pub const PATH_LENGTHS: usize = 64;
+
+// Counters for the render report. Every thread counts into its own copy, which flush()
+// adds to the totals once a tile or a batch of photons is done, so tracing never waits
+// on another thread. Nothing is counted until enable() is called, counting every BVH node
+// and timing every ray isn't free.
+#[derive(Debug, Clone)]
+pub struct Stats {
+    pub camera_rays: u64,
+    pub secondary_rays: u64,
+    pub light_rays: u64,
+    pub shadow_rays: u64,
+    pub node_visits: u64,
+    pub primitive_tests: u64,
+    pub samples: u64,
+    // samples whose radiance came out NaN or infinite, they count as black
+    pub invalid_samples: u64,
+    // camera samples by the number of rays along their camera paths, shadow rays and
+    // light subpaths aside
+    pub path_lengths: [u64; PATH_LENGTHS],
+    // time spent finding hits and taking camera samples as a whole, summed over threads
+    pub trace_time: Duration,
+    pub sample_time: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RayKind {
+    // leaves the camera
+    Camera,
+    // continues a path from the camera
+    Secondary,
+    // leaves a light or continues a path from one, for light subpaths and photons
+    Light,
+    // only asks what is in the way of a light
+    Shadow,
+}
+
+impl RayKind {
+    // the ray a path takes after `depth` bounces
+    pub fn path(depth: u32) -> Self {
+        if depth == 0 {
+            RayKind::Camera
+        } else {
+            RayKind::Secondary
+        }
+    }
+}
+
+thread_local! {
+    static LOCAL: RefCell<Stats> = const { RefCell::new(Stats::new()) };
+}
+
+static TOTAL: Mutex<Stats> = Mutex::new(Stats::new());
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+impl Stats {
+    pub const fn new() -> Self {
+        Self {
+            camera_rays: 0,
+            secondary_rays: 0,
+            light_rays: 0,
+            shadow_rays: 0,
+            node_visits: 0,
+            primitive_tests: 0,
+            samples: 0,
+            invalid_samples: 0,
+            path_lengths: [0; PATH_LENGTHS],
+            trace_time: Duration::ZERO,
+            sample_time: Duration::ZERO,
+        }
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.camera_rays + self.secondary_rays + self.light_rays + self.shadow_rays
+    }
+
+    fn add(&mut self, other: &Stats) {
+        self.camera_rays += other.camera_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.light_rays += other.light_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.node_visits += other.node_visits;
+        self.primitive_tests += other.primitive_tests;
+        self.samples += other.samples;
+        self.invalid_samples += other.invalid_samples;
+        for (total, n) in self.path_lengths.iter_mut().zip(&other.path_lengths) {
+            *total += n;
+        }
+        self.trace_time += other.trace_time;
+        self.sample_time += other.sample_time;
+    }
+
+    fn per_ray(&self, n: u64) -> f64 {
+        n as f64 / self.rays().max(1) as f64
+    }
+
+    // `elapsed` is the wall clock time of the render, for the rates, and `threads` how
+    // many threads worked on it
+    pub fn report(&self, elapsed: Duration, threads: usize) -> String {
+        let seconds = elapsed.as_secs_f64().max(1e-9);
+        let thread_seconds = seconds * threads as f64;
+        let mut report = String::new();
+
+        let _ = writeln!(report, "Render statistics:");
+        let _ = writeln!(
+            report,
+            "  time               {:.2} s",
+            elapsed.as_secs_f64()
+        );
+        let _ = writeln!(report, "  threads            {}", threads);
+        let _ = writeln!(report, "  samples            {}", self.samples);
+        let _ = writeln!(
+            report,
+            "  invalid samples    {} (NaN or infinite, counted as black)",
+            self.invalid_samples
+        );
+        let _ = writeln!(
+            report,
+            "  rays               {} ({:.2} M/s)",
+            self.rays(),
+            self.rays() as f64 / seconds / 1e6
+        );
+        let _ = writeln!(report, "    camera           {}", self.camera_rays);
+        let _ = writeln!(report, "    secondary        {}", self.secondary_rays);
+        let _ = writeln!(report, "    light            {}", self.light_rays);
+        let _ = writeln!(report, "    shadow           {}", self.shadow_rays);
+        let _ = writeln!(
+            report,
+            "  BVH nodes per ray  {:.2}",
+            self.per_ray(self.node_visits)
+        );
+        let _ = writeln!(
+            report,
+            "  primitive tests    {} ({:.2} per ray)",
+            self.primitive_tests,
+            self.per_ray(self.primitive_tests)
+        );
+        // the rest of the sampling time went into materials, lights and sampling
+        for (what, time) in [
+            ("camera samples", self.sample_time),
+            ("finding hits", self.trace_time),
+        ] {
+            let _ = writeln!(
+                report,
+                "  {:<18} {:.2} s, {:.1}% of the threads' time",
+                what,
+                time.as_secs_f64(),
+                100.0 * time.as_secs_f64() / thread_seconds
+            );
+        }
+
+        // the JSON has every length, here the long tail is lumped together
+        const SHOWN: usize = 16;
+        let _ = writeln!(report, "  path lengths");
+        for length in 0..=SHOWN {
+            let (n, more) = if length < SHOWN {
+                (self.path_lengths[length], " ")
+            } else {
+                (self.path_lengths[SHOWN..].iter().sum(), "+")
+            };
+            if n > 0 {
+                let share = 100.0 * n as f64 / self.samples.max(1) as f64;
+                let _ = writeln!(report, "    {:>3}{} {:>12} {:5.1}%", length, more, n, share);
+            }
+        }
+
+        report
+    }
+
+    // the same numbers as report(), for scripts
+    pub fn to_json(&self, elapsed: Duration, threads: usize) -> String {
+        let path_lengths = self
+            .path_lengths
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut json = String::new();
+        let _ = writeln!(json, "{{");
+        let _ = writeln!(json, "  \"seconds\": {},", elapsed.as_secs_f64());
+        let _ = writeln!(json, "  \"threads\": {},", threads);
+        let _ = writeln!(json, "  \"samples\": {},", self.samples);
+        let _ = writeln!(json, "  \"invalid_samples\": {},", self.invalid_samples);
+        let _ = writeln!(json, "  \"rays\": {},", self.rays());
+        let _ = writeln!(
+            json,
+            "  \"rays_per_second\": {},",
+            self.rays() as f64 / elapsed.as_secs_f64().max(1e-9)
+        );
+        let _ = writeln!(json, "  \"camera_rays\": {},", self.camera_rays);
+        let _ = writeln!(json, "  \"secondary_rays\": {},", self.secondary_rays);
+        let _ = writeln!(json, "  \"light_rays\": {},", self.light_rays);
+        let _ = writeln!(json, "  \"shadow_rays\": {},", self.shadow_rays);
+        let _ = writeln!(json, "  \"bvh_node_visits\": {},", self.node_visits);
+        let _ = writeln!(json, "  \"primitive_tests\": {},", self.primitive_tests);
+        let _ = writeln!(
+            json,
+            "  \"trace_seconds\": {},",
+            self.trace_time.as_secs_f64()
+        );
+        let _ = writeln!(
+            json,
+            "  \"sample_seconds\": {},",
+            self.sample_time.as_secs_f64()
+        );
+        let _ = writeln!(json, "  \"path_lengths\": [{}]", path_lengths);
+        let _ = writeln!(json, "}}");
+        json
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn count(f: impl FnOnce(&mut Stats)) {
+    if enabled() {
+        LOCAL.with(|local| f(&mut local.borrow_mut()));
+    }
+}
+
+// Closest hit along `ray` up to `t_max`, counted as a ray of `kind`.
+pub fn trace<'a>(
+    world: &'a dyn Hittable,
+    ray: &Ray,
+    t_max: f32,
+    kind: RayKind,
+) -> Option<HitRecord<'a>> {
+    if !enabled() {
+        return world.hit(ray, 0.001, t_max);
+    }
+
+    let start = Instant::now();
+    let hit = world.hit(ray, 0.001, t_max);
+    let time = start.elapsed();
+
+    count(|stats| {
+        match kind {
+            RayKind::Camera => stats.camera_rays += 1,
+            RayKind::Secondary => stats.secondary_rays += 1,
+            RayKind::Light => stats.light_rays += 1,
+            RayKind::Shadow => stats.shadow_rays += 1,
+        }
+        stats.trace_time += time;
+    });
+    hit
+}
+
+pub fn node_visit() {
+    count(|stats| stats.node_visits += 1);
+}
+
+pub fn primitive_test() {
+    count(|stats| stats.primitive_tests += 1);
+}
+
+// BVH nodes this thread visited since its last flush
+pub fn local_node_visits() -> u64 {
+    LOCAL.with(|local| local.borrow().node_visits)
+}
+
+// One camera sample being taken, finish() it with the radiance it came back with.
+pub struct Sample {
+    // None while counting is off
+    start: Option<Instant>,
+    path_rays: u64,
+}
+
+pub fn start_sample() -> Sample {
+    Sample {
+        start: enabled().then(Instant::now),
+        path_rays: LOCAL.with(|local| path_rays(&local.borrow())),
+    }
+}
+
+impl Sample {
+    pub fn finish(self, valid: bool) {
+        let time = self.start.map_or(Duration::ZERO, |start| start.elapsed());
+        count(|stats| {
+            let length = (path_rays(stats) - self.path_rays) as usize;
+            stats.path_lengths[length.min(PATH_LENGTHS - 1)] += 1;
+            stats.samples += 1;
+            if !valid {
+                stats.invalid_samples += 1;
+            }
+            stats.sample_time += time;
+        });
+    }
+}
+
+// rays along camera paths, a BDPT sample's light subpath doesn't make its path longer
+fn path_rays(stats: &Stats) -> u64 {
+    stats.camera_rays + stats.secondary_rays
+}
+
+// adds what this thread counted to the totals
+pub fn flush() {
+    if !enabled() {
+        return;
+    }
+
+    let local = LOCAL.with(|local| std::mem::take(&mut *local.borrow_mut()));
+    TOTAL
+        .lock()
+        .expect("a thread panicked while flushing stats")
+        .add(&local);
+}
+
+// everything counted so far, on any thread that flushed
+pub fn totals() -> Stats {
+    flush();
+    TOTAL
+        .lock()
+        .expect("a thread panicked while flushing stats")
+        .clone()
+}
@@ -1,6 +1,8 @@
 use crate::perlin::Perlin;
 use crate::vec3::{Color, Point3, Vec3};
 
+use rand::Rng;
+
 pub trait Texture: Sync + Send {
     fn value(&self, u: f32, v: f32, p: Point3) -> Color;
 }
@@ -128,9 +130,9 @@ pub struct NoiseTexture {
 }
 
 impl NoiseTexture {
-    pub fn new(scale: f32) -> Self {
+    pub fn new(scale: f32, rng: &mut impl Rng) -> Self {
         Self {
-            noise: Perlin::new(),
+            noise: Perlin::new(rng),
             scale,
         }
     }
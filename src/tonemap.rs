@@ -0,0 +1,179 @@
+// Turns linear radiance into display values for 8 and 16 bit output: exposure, then a tone
+// mapping curve that squeezes the range into [0, 1], then the sRGB transfer function.
+
+use crate::vec3::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapper {
+    // hard clip at 1
+    Clamp,
+    Reinhard,
+    // Reinhard that maps the white point to 1 instead of approaching it
+    ExtendedReinhard,
+    // Stephen Hill's fit of the ACES reference and sRGB output transforms
+    Aces,
+    // Troy Sobotka's AgX with the base look, from Benjamin Wrensch's polynomial fit
+    Agx,
+}
+
+impl ToneMapper {
+    pub const NAMES: &'static [&'static str] =
+        &["clamp", "reinhard", "reinhard-extended", "aces", "agx"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(ToneMapper::Clamp),
+            "reinhard" => Some(ToneMapper::Reinhard),
+            "reinhard-extended" => Some(ToneMapper::ExtendedReinhard),
+            "aces" => Some(ToneMapper::Aces),
+            "agx" => Some(ToneMapper::Agx),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapping {
+    pub operator: ToneMapper,
+    // exposure compensation in stops
+    pub exposure: f32,
+    // luminance that extended Reinhard maps to white, the brightest pixel if None
+    pub white_point: Option<f32>,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapper::Clamp,
+            exposure: 0.0,
+            white_point: None,
+        }
+    }
+}
+
+impl ToneMapping {
+    // sRGB encoded values in [0, 1] for every pixel
+    pub fn apply(&self, pixels: &[Color]) -> Vec<Color> {
+        let scale = 2f32.powf(self.exposure);
+        let white = self.white_point.unwrap_or_else(|| {
+            pixels
+                .iter()
+                .map(|c| scale * c.luminance())
+                .fold(0.0, f32::max)
+        });
+
+        pixels
+            .iter()
+            .map(|&c| {
+                let c = self.map(scale * c, white);
+                Color::new(
+                    srgb_oetf(Vec3::clamp(c.x, 0.0, 1.0)),
+                    srgb_oetf(Vec3::clamp(c.y, 0.0, 1.0)),
+                    srgb_oetf(Vec3::clamp(c.z, 0.0, 1.0)),
+                )
+            })
+            .collect()
+    }
+
+    // linear display values, clamped by the caller
+    fn map(&self, c: Color, white: f32) -> Color {
+        match self.operator {
+            ToneMapper::Clamp => c,
+            ToneMapper::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+            ToneMapper::ExtendedReinhard => {
+                let white = white.max(1e-4);
+                scale_luminance(c, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            ToneMapper::Aces => aces(c),
+            ToneMapper::Agx => agx(c),
+        }
+    }
+}
+
+// Curves on luminance keep the hue, per channel ones would drift bright colours to white.
+fn scale_luminance(c: Color, curve: impl Fn(f32) -> f32) -> Color {
+    let l = c.luminance();
+    if l <= 0.0 {
+        return Color::new_empty();
+    }
+    c * (curve(l) / l)
+}
+
+fn aces(c: Color) -> Color {
+    // sRGB to the working space of the RRT, with its saturation adjustment
+    let c = mat3(
+        [
+            [0.59719, 0.35458, 0.04823],
+            [0.07600, 0.90834, 0.01566],
+            [0.02840, 0.13383, 0.83777],
+        ],
+        c,
+    );
+
+    let fit =
+        |v: f32| (v * (v + 0.0245786) - 9.0537e-05) / (v * (0.983729 * v + 0.432951) + 0.238081);
+    let c = Color::new(fit(c.x), fit(c.y), fit(c.z));
+
+    mat3(
+        [
+            [1.60475, -0.53108, -0.07367],
+            [-0.10208, 1.10813, -0.00605],
+            [-0.00327, -0.07276, 1.07602],
+        ],
+        c,
+    )
+}
+
+fn agx(c: Color) -> Color {
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let c = mat3(
+        [
+            [0.84247905, 0.0784336, 0.079223745],
+            [0.042328242, 0.87846863, 0.07916613],
+            [0.042375654, 0.0784336, 0.879143],
+        ],
+        c,
+    );
+
+    // log encoding, then the sigmoid
+    let curve = |v: f32| {
+        let x = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    };
+    let c = Color::new(curve(c.x), curve(c.y), curve(c.z));
+
+    let c = mat3(
+        [
+            [1.196879, -0.09802088, -0.09902974],
+            [-0.052896854, 1.1519032, -0.098961174],
+            [-0.052971635, -0.09804345, 1.1510737],
+        ],
+        c,
+    );
+
+    // the curve's output is display encoded with a 2.2 gamma, back to linear
+    let linear = |v: f32| v.max(0.0).powf(2.2);
+    Color::new(linear(c.x), linear(c.y), linear(c.z))
+}
+
+// row-major matrix times column vector
+fn mat3(m: [[f32; 3]; 3], c: Color) -> Color {
+    Color::new(
+        m[0][0] * c.x + m[0][1] * c.y + m[0][2] * c.z,
+        m[1][0] * c.x + m[1][1] * c.y + m[1][2] * c.z,
+        m[2][0] * c.x + m[2][1] * c.y + m[2][2] * c.z,
+    )
+}
+
+pub fn srgb_oetf(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -2,6 +2,7 @@ use crate::aabb::AABB;
 use crate::hittable::*;
 use crate::matrix4::Matrix4;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::*;
 
 use std::f32;
@@ -26,21 +27,29 @@ impl Transform {
             inv_transform: transform_mat.inverse().unwrap(),
         }
     }
-}
 
-impl Hittable for Transform {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let transformed_ray = Ray::new(
+    fn transform_ray(&self, r: &Ray) -> Ray {
+        Ray::new(
             self.transform_mat * r.orig,
             self.transform_mat.mul_as_33(r.dir),
             r.time,
-        );
-        if let Some(mut hit) = self.hit.hit(&transformed_ray, t_min, t_max) {
-            hit.p = self.inv_transform * r.orig;
-            hit.normal = self.inv_transform.mul_as_33(hit.normal);
-            return Some(hit);
-        }
-        None
+        )
+    }
+
+    // a hit on the inner object back in the world
+    fn place<'a>(&self, mut hit: HitRecord<'a>, r: &Ray) -> HitRecord<'a> {
+        hit.p = self.inv_transform * r.orig;
+        hit.normal = self.inv_transform.mul_as_33(hit.normal);
+        hit
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let transformed_ray = self.transform_ray(r);
+        self.hit
+            .hit(&transformed_ray, t_min, t_max)
+            .map(|hit| self.place(hit, r))
     }
 
     fn bounding_box(&self, time0: f32, time1: f32) -> Option<AABB> {
@@ -75,6 +84,17 @@ impl Hittable for Transform {
 
         None
     }
+
+    fn object_count(&self) -> usize {
+        self.hit.object_count()
+    }
+
+    fn hit_object(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord<'_>, usize)> {
+        let transformed_ray = self.transform_ray(r);
+        self.hit
+            .hit_object(&transformed_ray, t_min, t_max)
+            .map(|(hit, i)| (self.place(hit, r), i))
+    }
 }
 
 pub struct Translate {
@@ -89,29 +109,44 @@ impl Translate {
             offset,
         }
     }
+
+    // a hit on the inner object, found with `moved_r`, back where it was moved to
+    fn place<'a>(&self, mut rec: HitRecord<'a>, moved_r: &Ray) -> HitRecord<'a> {
+        rec.p += self.offset;
+        rec.set_face_normal(moved_r, rec.normal);
+        rec
+    }
 }
 
 impl Hittable for Translate {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let moved_r = Ray::new(r.orig - self.offset, r.dir, r.time);
-        if let Some(mut rec) = self.hit.hit(&moved_r, t_min, t_max) {
-            rec.p += self.offset;
-            rec.set_face_normal(&moved_r, rec.normal);
-            Some(rec)
-        } else {
-            None
-        }
+        self.hit
+            .hit(&moved_r, t_min, t_max)
+            .map(|rec| self.place(rec, &moved_r))
     }
 
     fn bounding_box(&self, time0: f32, time1: f32) -> Option<AABB> {
-        if let Some(output_box) = self.hit.bounding_box(time0, time1) {
-            Some(AABB::new(
-                output_box.min + self.offset,
-                output_box.max + self.offset,
-            ))
-        } else {
-            None
-        }
+        self.hit
+            .bounding_box(time0, time1)
+            .map(|output_box| AABB::new(output_box.min + self.offset, output_box.max + self.offset))
+    }
+
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let (mut rec, pdf) = self.hit.sample_surface(sampler)?;
+        rec.p += self.offset;
+        Some((rec, pdf))
+    }
+
+    fn object_count(&self) -> usize {
+        self.hit.object_count()
+    }
+
+    fn hit_object(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord<'_>, usize)> {
+        let moved_r = Ray::new(r.orig - self.offset, r.dir, r.time);
+        self.hit
+            .hit_object(&moved_r, t_min, t_max)
+            .map(|(rec, i)| (self.place(rec, &moved_r), i))
     }
 }
 
@@ -180,16 +215,19 @@ impl Rotate {
             axis,
         }
     }
-}
 
-impl Hittable for Rotate {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let (a, b) = match self.axis {
+    // the two coordinates the rotation mixes
+    fn plane(&self) -> (usize, usize) {
+        match self.axis {
             Axis::X => (1, 2),
             Axis::Y => (0, 2),
             Axis::Z => (0, 1),
-        };
+        }
+    }
 
+    // a world space ray in the inner object's space
+    fn rotate_ray(&self, r: &Ray) -> Ray {
+        let (a, b) = self.plane();
         let mut origin = r.orig;
         let mut dir = r.dir;
 
@@ -199,39 +237,66 @@ impl Hittable for Rotate {
         dir[a] = self.cos_theta * r.dir[a] - self.sin_theta * r.dir[b];
         dir[b] = self.sin_theta * r.dir[a] + self.cos_theta * r.dir[b];
 
-        let rotated_r = Ray::new(origin, dir, r.time);
-
-        if let Some(rec) = self.hit.hit(&rotated_r, t_min, t_max) {
-            let mut p = rec.p;
-            let mut normal = rec.normal;
-
-            p[a] = self.cos_theta * rec.p[a] + self.sin_theta * rec.p[b];
-            p[b] = -self.sin_theta * rec.p[a] + self.cos_theta * rec.p[b];
+        Ray::new(origin, dir, r.time)
+    }
 
-            normal[a] = self.cos_theta * rec.normal[a] + self.sin_theta * rec.normal[b];
-            normal[b] = -self.sin_theta * rec.normal[a] + self.cos_theta * rec.normal[b];
+    // a point or direction in the inner object's space back in the world
+    fn unrotate(&self, v: Vec3) -> Vec3 {
+        let (a, b) = self.plane();
+        let mut out = v;
+        out[a] = self.cos_theta * v[a] + self.sin_theta * v[b];
+        out[b] = -self.sin_theta * v[a] + self.cos_theta * v[b];
+        out
+    }
 
-            let mut ret = HitRecord {
-                p,
-                normal,
-                t: rec.t,
-                u: rec.u,
-                v: rec.v,
-                front_face: true,
-                material: rec.material,
-            };
+    // a hit on the inner object, found with `rotated_r`, back in the world
+    fn place<'a>(&self, rec: HitRecord<'a>, rotated_r: &Ray) -> HitRecord<'a> {
+        let normal = self.unrotate(rec.normal);
+        let mut ret = HitRecord {
+            p: self.unrotate(rec.p),
+            normal,
+            t: rec.t,
+            u: rec.u,
+            v: rec.v,
+            front_face: true,
+            material: rec.material,
+        };
 
-            ret.set_face_normal(&rotated_r, normal);
+        ret.set_face_normal(rotated_r, normal);
+        ret
+    }
+}
 
-            Some(ret)
-        } else {
-            None
-        }
+impl Hittable for Rotate {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let rotated_r = self.rotate_ray(r);
+        self.hit
+            .hit(&rotated_r, t_min, t_max)
+            .map(|rec| self.place(rec, &rotated_r))
     }
 
     fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<AABB> {
         Some(self.bbox.clone())
     }
+
+    // rotating doesn't change areas, so the density carries over
+    fn sample_surface(&self, sampler: &mut dyn Sampler) -> Option<(HitRecord<'_>, f32)> {
+        let (mut rec, pdf) = self.hit.sample_surface(sampler)?;
+        rec.p = self.unrotate(rec.p);
+        rec.normal = self.unrotate(rec.normal);
+        Some((rec, pdf))
+    }
+
+    fn object_count(&self) -> usize {
+        self.hit.object_count()
+    }
+
+    fn hit_object(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(HitRecord<'_>, usize)> {
+        let rotated_r = self.rotate_ray(r);
+        self.hit
+            .hit_object(&rotated_r, t_min, t_max)
+            .map(|(rec, i)| (self.place(rec, &rotated_r), i))
+    }
 }
 
 /*
@@ -247,7 +312,7 @@ impl Scale {
 }
 
 impl Hittable for Scale {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         if let Some(mut rec) = self.hit.hit(&r, t_min, t_max) {
             rec.set_face_normal(&r, rec.normal);
             Some(rec)
@@ -2,6 +2,7 @@ use crate::aabb::AABB;
 use crate::hittable::*;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::stats;
 use crate::vec3::*;
 
 // https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/ray-triangle-intersection-geometric-solution
@@ -36,7 +37,8 @@ impl<M: Material> Triangle<M> {
 }
 
 impl<M: Sync + Send + Material + 'static> Hittable for Triangle<M> {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        stats::primitive_test();
         let v0v1 = self.v1 - self.v0;
         let v0v2 = self.v2 - self.v0;
 
@@ -52,7 +54,7 @@ impl<M: Sync + Send + Material + 'static> Hittable for Triangle<M> {
 
         let tvec = r.orig - self.v0;
         let u = tvec.dot(p) * inv_det;
-        if u < 0.0 || u > 1.0 {
+        if !(0.0..=1.0).contains(&u) {
             return None;
         }
 
@@ -3,7 +3,7 @@ use std::ops;
 use rand;
 use rand::prelude::*;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -163,16 +163,18 @@ impl Vec3 {
         Self { x, y, z }
     }
 
-    pub fn random() -> Self {
+    // The random_* functions are for building scenes, with the seeded generator the scene
+    // gets. Rendering takes its random numbers from a sampler instead.
+
+    pub fn random(rng: &mut impl Rng) -> Self {
         Self {
-            x: rand::random(),
-            y: rand::random(),
-            z: rand::random(),
+            x: rng.gen(),
+            y: rng.gen(),
+            z: rng.gen(),
         }
     }
 
-    pub fn random_range(min: f32, max: f32) -> Self {
-        let mut rng = thread_rng();
+    pub fn random_range(min: f32, max: f32, rng: &mut impl Rng) -> Self {
         Self {
             x: rng.gen_range(min..max),
             y: rng.gen_range(min..max),
@@ -180,8 +182,7 @@ impl Vec3 {
         }
     }
 
-    pub fn random_range_i32(min: i32, max: i32) -> Self {
-        let mut rng = thread_rng();
+    pub fn random_range_i32(min: i32, max: i32, rng: &mut impl Rng) -> Self {
         Self {
             x: rng.gen_range(min..max) as f32,
             y: rng.gen_range(min..max) as f32,
@@ -217,50 +218,25 @@ impl Vec3 {
         *self / self.length()
     }
 
-    pub fn random_unit_vector() -> Self {
-        Self::random_in_unit_sphere().unit_vector()
-    }
-
     pub fn near_zero(&self) -> bool {
         let s = 1e-8;
         (self.x.abs() < s) && (self.y.abs() < s) && (self.z.abs() < s)
     }
 
-    pub fn random_in_unit_disk() -> Self {
-        let mut rng = thread_rng();
+    pub fn random_in_unit_disk(rng: &mut impl Rng) -> Self {
         let mut p;
         loop {
             p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-            if p.length_squared() >= 1.0 {
+            if p.length_squared() < 1.0 {
                 break;
             }
         }
         p
     }
 
-    pub fn calc_color(pixel_color: Color, samples_per_pixel: usize) -> Vec3 {
-        let mut ret = pixel_color;
-
-        if ret.x.is_nan() {
-            ret.x = 0.0;
-        }
-        if ret.y.is_nan() {
-            ret.y = 0.0;
-        }
-        if ret.z.is_nan() {
-            ret.z = 0.0;
-        }
-
-        let scale = 1.0 / samples_per_pixel as f32;
-        ret.x = (scale * ret.x).sqrt();
-        ret.y = (scale * ret.y).sqrt();
-        ret.z = (scale * ret.z).sqrt();
-
-        ret.x = 256.0 * Self::clamp(ret.x, 0.0, 0.99);
-        ret.y = 256.0 * Self::clamp(ret.y, 0.0, 0.99);
-        ret.z = 256.0 * Self::clamp(ret.z, 0.0, 0.99);
-
-        ret
+    // Rec. 709 relative luminance of a linear color
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
     }
 
     pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
@@ -273,8 +249,7 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_sphere() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn random_in_unit_sphere(rng: &mut impl Rng) -> Self {
         let unit = Vec3::new(1.0, 1.0, 1.0);
         loop {
             let p = 2.0 * Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) - unit;
@@ -284,8 +259,8 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_hemisphere(normal: Self) -> Self {
-        let in_unit = Self::random_in_unit_sphere();
+    pub fn random_in_hemisphere(normal: Self, rng: &mut impl Rng) -> Self {
+        let in_unit = Self::random_in_unit_sphere(rng);
         if in_unit.dot(normal) > 0.0 {
             in_unit
         } else {
@@ -293,10 +268,12 @@ impl Vec3 {
         }
     }
 
-    pub fn random_cosine_dir() -> Self {
-        let r1 = rand::random::<f32>();
-        let r2 = rand::random::<f32>();
+    // Warps of a uniform sample `u` in [0, 1)^2 from a sampler. They keep nearby samples
+    // nearby, so well spread samples give well spread points.
 
+    // cosine weighted direction around +z
+    pub fn sample_cosine_dir(u: (f32, f32)) -> Self {
+        let (r1, r2) = u;
         let z = (1.0 - r2).sqrt();
 
         let phi = 2.0 * std::f32::consts::PI * r1;
@@ -306,6 +283,38 @@ impl Vec3 {
         Self { x, y, z }
     }
 
+    // uniformly distributed direction
+    pub fn sample_unit_vector(u: (f32, f32)) -> Self {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * u.1;
+
+        Self::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    // point in the unit ball, `w` picks the distance from the centre
+    pub fn sample_unit_ball(u: (f32, f32), w: f32) -> Self {
+        w.cbrt() * Self::sample_unit_vector(u)
+    }
+
+    // Point in the unit disk on the z = 0 plane, with Shirley and Chiu's concentric
+    // mapping of the square.
+    pub fn sample_unit_disk(u: (f32, f32)) -> Self {
+        let (a, b) = (2.0 * u.0 - 1.0, 2.0 * u.1 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Self::new_empty();
+        }
+
+        let quarter_pi = std::f32::consts::FRAC_PI_4;
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, quarter_pi * (b / a))
+        } else {
+            (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+        };
+
+        Self::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
     pub fn is_nan(&self) -> bool {
         if self.x.is_nan() {
             return true;
@@ -319,6 +328,10 @@ impl Vec3 {
 
         false
     }
+
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
 }
 
 impl IntoIterator for Vec3 {